@@ -0,0 +1,72 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{Path, Token};
+
+pub fn expand(input: TokenStream) -> TokenStream {
+    let commands = match Punctuated::<Path, Token![,]>::parse_terminated.parse(input) {
+        Ok(commands) => commands.into_iter().collect::<Vec<_>>(),
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let expanded = quote! {
+        /// Get all registered commands for Discord API registration
+        pub fn all_commands() -> Vec<crate::commands::SlashCommand> {
+            vec![
+                #( <#commands as crate::commands::Command>::definition(), )*
+            ]
+        }
+
+        /// Route an application command to its handler by name
+        async fn dispatch_command(
+            name: &str,
+            interaction: crate::discord::discord::Interaction,
+        ) -> Result<(), crate::error::BotError> {
+            #(
+                if name == <#commands as crate::commands::Command>::NAME {
+                    return <#commands as crate::commands::Command>::handle(interaction).await;
+                }
+            )*
+            Ok(()) // Unknown command, ignore
+        }
+
+        /// Route an autocomplete request to its command by name
+        async fn dispatch_autocomplete(
+            name: &str,
+            interaction: crate::discord::discord::Interaction,
+        ) -> Result<Vec<crate::commands::CommandOptionChoice>, crate::error::BotError> {
+            #(
+                if name == <#commands as crate::commands::Command>::NAME {
+                    return <#commands as crate::commands::Command>::autocomplete(interaction).await;
+                }
+            )*
+            Ok(Vec::new())
+        }
+
+        /// Job kind for a mention keyword, e.g. `bg` in `@Raft bg`
+        pub fn kind_for_alias(alias: &str) -> Option<crate::discord::message::handle::CommandKind> {
+            #(
+                if <#commands as crate::commands::Command>::ALIASES.contains(&alias) {
+                    return <#commands as crate::commands::Command>::KIND;
+                }
+            )*
+            None
+        }
+
+        /// Job kind and slash definition for a mention keyword, whose options the mention mirrors
+        pub fn command_for_alias(
+            alias: &str,
+        ) -> Option<(crate::discord::message::handle::CommandKind, crate::commands::SlashCommand)> {
+            #(
+                if <#commands as crate::commands::Command>::ALIASES.contains(&alias) {
+                    return <#commands as crate::commands::Command>::KIND
+                        .map(|kind| (kind, <#commands as crate::commands::Command>::definition()));
+                }
+            )*
+            None
+        }
+    };
+
+    TokenStream::from(expanded)
+}
@@ -2,9 +2,16 @@ extern crate proc_macro;
 
 use proc_macro::TokenStream;
 
+mod command_registry;
 mod define_field_names;
 
 #[proc_macro_attribute]
 pub fn define_field_names(attr: TokenStream, item: TokenStream) -> TokenStream {
     define_field_names::expand(attr, item)
 }
+
+/// Build `all_commands`, name dispatch and mention aliases from one list of `Command` types
+#[proc_macro]
+pub fn command_registry(input: TokenStream) -> TokenStream {
+    command_registry::expand(input)
+}
@@ -1,5 +1,7 @@
 use image::DynamicImage;
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use strum::{AsRefStr, EnumString};
 
 pub static ICON_ATC: Lazy<DynamicImage> = Lazy::new(|| {
     let data = include_bytes!("../assets/icon/classic/ATC.png");
@@ -29,4 +31,35 @@ pub static ICON_DISPAS: Lazy<DynamicImage> = Lazy::new(|| {
 pub static ICON_PAS: Lazy<DynamicImage> = Lazy::new(|| {
     let data = include_bytes!("../assets/icon/classic/PAS.png");
     image::load_from_memory(data).expect("Failed to load PAS.png")
-});
\ No newline at end of file
+});
+
+/// Border variants of an icon in collage order, named by their file prefix
+pub const BORDER_VARIANTS: [&str; 6] = ["BTN", "DISBTN", "ATC", "DISATC", "PAS", "DISPAS"];
+
+/// Classic overlays in `BORDER_VARIANTS` order
+static CLASSIC_OVERLAYS: [&Lazy<DynamicImage>; 6] =
+    [&ICON_BTN, &ICON_DISBTN, &ICON_ATC, &ICON_DISATC, &ICON_PAS, &ICON_DISPAS];
+
+/// Frame drawn over generated icons, chosen per server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum FrameStyle {
+    /// Classic Warcraft III borders from `assets/icon/classic`
+    #[default]
+    Classic,
+    /// No frame baked in, for clients that draw their own
+    None,
+}
+
+impl FrameStyle {
+    /// Overlay for one of the `BORDER_VARIANTS`, or None when nothing is drawn over the icon
+    pub fn overlay(self, variant: &str) -> Option<&'static DynamicImage> {
+        let index = BORDER_VARIANTS.iter().position(|v| *v == variant)?;
+
+        match self {
+            Self::Classic => Some(&**CLASSIC_OVERLAYS[index]),
+            Self::None => None,
+        }
+    }
+}
@@ -0,0 +1,490 @@
+//! Buttons, selects and modals attached to job results, and the handlers that run when they're used
+
+use crate::assets::BORDER_VARIANTS;
+use crate::commands::{enqueue_deferred, interaction_job_message};
+use crate::db::guild_config::GuildConfig;
+use crate::discord::api;
+use crate::discord::discord::{Interaction, InteractionData, InteractionTarget, ModalData};
+use crate::discord::message::attachment::AttachmentVecExt;
+use crate::discord::message::component::{
+    ActionRow, BUTTON_PRIMARY, BUTTON_SECONDARY, Component, SelectOption,
+};
+use crate::discord::message::handle::{CommandArgs, CommandKind};
+use crate::discord::message::message::Message;
+use crate::discord::message::send::MessageSend;
+use crate::error::BotError;
+use crate::state;
+use crate::workers::blp::job::{ConversionTarget, JobBlp};
+use crate::workers::icon::job::JobIcon;
+use crate::workers::job::{Job, zip_files};
+use crate::workers::rembg::job::JobRembg;
+use mongodb::bson::oid::ObjectId;
+
+/// Quality offered by the "Re-run" button on BLP results
+const RERUN_QUALITY: u8 = 95;
+
+/// Actions for a finished BLP/PNG conversion
+pub fn blp_actions(
+    job_id: Option<ObjectId>,
+    target: &ConversionTarget,
+    quality: u8,
+    zipped: bool,
+    file_count: usize,
+) -> Option<Vec<ActionRow>> {
+    let job_id = job_id?.to_hex();
+    let mut buttons = Vec::new();
+
+    if *target == ConversionTarget::Blp && quality < RERUN_QUALITY {
+        buttons.push(Component::button(
+            format!("blp:rerun:{}:{}", job_id, RERUN_QUALITY),
+            &format!("Re-run at quality {}", RERUN_QUALITY),
+            BUTTON_PRIMARY,
+        ));
+    }
+
+    if !zipped && file_count > 1 {
+        buttons.push(Component::button(
+            format!("blp:zip:{}", job_id),
+            "Download as ZIP",
+            BUTTON_SECONDARY,
+        ));
+    }
+
+    if *target == ConversionTarget::Blp {
+        buttons.push(Component::button(
+            format!("blp:advanced:{}", job_id),
+            "⚙️ Advanced…",
+            BUTTON_SECONDARY,
+        ));
+    }
+
+    (!buttons.is_empty()).then(|| vec![ActionRow::new(buttons)])
+}
+
+/// Actions for a finished background removal
+pub fn rembg_actions(
+    job_id: Option<ObjectId>,
+    zipped: bool,
+    file_count: usize,
+) -> Option<Vec<ActionRow>> {
+    if zipped || file_count < 2 {
+        return None;
+    }
+
+    Some(vec![ActionRow::new(vec![Component::button(
+        format!("rembg:zip:{}", job_id?.to_hex()),
+        "Download as ZIP",
+        BUTTON_SECONDARY,
+    )])])
+}
+
+/// Actions for a finished icon job: pick which border variants to render, or open advanced settings
+pub fn icon_actions(job_id: Option<ObjectId>) -> Option<Vec<ActionRow>> {
+    let job_id = job_id?.to_hex();
+
+    let options = BORDER_VARIANTS
+        .iter()
+        .map(|variant| SelectOption::new(variant, variant))
+        .collect();
+
+    Some(vec![
+        ActionRow::new(vec![Component::multi_select(
+            format!("icon:variants:{}", job_id),
+            "Render only these borders",
+            options,
+        )]),
+        ActionRow::new(vec![Component::button(
+            format!("icon:advanced:{}", job_id),
+            "⚙️ Advanced…",
+            BUTTON_SECONDARY,
+        )]),
+    ])
+}
+
+/// Route a MESSAGE_COMPONENT interaction by its custom_id
+pub async fn handle_component(interaction: Interaction) -> Result<(), BotError> {
+    let Some(custom_id) = interaction.data.as_ref().and_then(|d| d.custom_id.clone()) else {
+        return Ok(());
+    };
+
+    let parts: Vec<&str> = custom_id.split(':').collect();
+
+    match parts.as_slice() {
+        ["blp", "rerun", job_id, quality] => {
+            let quality = quality.parse::<u8>().ok();
+            rerun_blp(&interaction, job_id, |args| {
+                if let Some(quality) = quality {
+                    args.quality = quality.clamp(1, 100);
+                }
+            })
+            .await
+        }
+        ["blp", "zip", job_id] => {
+            let job = JobBlp::find_by_id(&*state::db().await, job_id).await?;
+            zip_results(&interaction, job.as_ref()).await
+        }
+        ["blp", "advanced", job_id] => open_blp_settings(&interaction, job_id).await,
+        ["rembg", "zip", job_id] => {
+            let job = JobRembg::find_by_id(&*state::db().await, job_id).await?;
+            zip_results(&interaction, job.as_ref()).await
+        }
+        ["icon", "variants", job_id] => {
+            let variants = interaction
+                .data
+                .as_ref()
+                .map(|data| data.values.clone())
+                .unwrap_or_default();
+            rerun_icon(&interaction, job_id, |args| args.variants = variants).await
+        }
+        ["icon", "advanced", job_id] => open_icon_settings(&interaction, job_id).await,
+        _ => Ok(()), // Unknown component, ignore
+    }
+}
+
+/// Route a MODAL_SUBMIT interaction by its custom_id
+pub async fn handle_modal_submit(interaction: Interaction) -> Result<(), BotError> {
+    let Some(data) = &interaction.data else {
+        return Ok(());
+    };
+    let Some(custom_id) = data.custom_id.clone() else {
+        return Ok(());
+    };
+
+    let parts: Vec<&str> = custom_id.split(':').collect();
+
+    match parts.as_slice() {
+        ["blp", "advanced", job_id] => {
+            let settings = match parse_blp_settings(data) {
+                Ok(settings) => settings,
+                Err(message) => return invalid_settings(&interaction, message).await,
+            };
+
+            rerun_blp(&interaction, job_id, |args| {
+                if let Some(quality) = settings.quality {
+                    args.quality = quality;
+                }
+                args.mip_count = settings.mip_count;
+                args.alpha_depth = settings.alpha_depth;
+            })
+            .await
+        }
+        ["icon", "advanced", job_id] => {
+            let settings = match parse_icon_settings(data) {
+                Ok(settings) => settings,
+                Err(message) => return invalid_settings(&interaction, message).await,
+            };
+
+            rerun_icon(&interaction, job_id, |args| {
+                args.crop_x = settings.crop_x;
+                args.crop_y = settings.crop_y;
+                args.output_path = settings.output_path;
+            })
+            .await
+        }
+        _ => Ok(()), // Unknown modal, ignore
+    }
+}
+
+/// Re-run a BLP/PNG job with the original settings, adjusted by `adjust`
+async fn rerun_blp(
+    interaction: &Interaction,
+    job_id: &str,
+    adjust: impl FnOnce(&mut CommandArgs),
+) -> Result<(), BotError> {
+    let Some(job) = JobBlp::find_by_id(&*state::db().await, job_id).await? else {
+        return job_unavailable(interaction).await;
+    };
+    if !is_author(interaction, &job.message) {
+        return not_author(interaction).await;
+    }
+
+    let Some(message) = interaction_job_message(interaction, job.message.attachments) else {
+        return no_job_message(interaction).await;
+    };
+
+    let mut args = CommandArgs {
+        kind: match job.target {
+            ConversionTarget::Blp => CommandKind::Blp,
+            ConversionTarget::Png => CommandKind::Png,
+        },
+        quality: job.quality,
+        zip: job.zip,
+        mip_count: job.mip_count,
+        alpha_depth: job.alpha_depth,
+        ..Default::default()
+    };
+    adjust(&mut args);
+
+    let config = GuildConfig::for_guild(&*state::db().await, message.guild_id.as_deref()).await?;
+    enqueue_deferred(interaction, &config, message, args).await
+}
+
+/// Re-run an icon job with the original settings, adjusted by `adjust`
+async fn rerun_icon(
+    interaction: &Interaction,
+    job_id: &str,
+    adjust: impl FnOnce(&mut CommandArgs),
+) -> Result<(), BotError> {
+    let Some(job) = JobIcon::find_by_id(&*state::db().await, job_id).await? else {
+        return job_unavailable(interaction).await;
+    };
+    if !is_author(interaction, &job.message) {
+        return not_author(interaction).await;
+    }
+
+    let mut args = CommandArgs {
+        kind: CommandKind::Icon,
+        crop_x: job.crop_x,
+        crop_y: job.crop_y,
+        output_path: job.output_path,
+        frame_style: job.frame_style,
+        variants: job.variants,
+        ..Default::default()
+    };
+    adjust(&mut args);
+
+    let Some(message) = interaction_job_message(interaction, job.message.attachments) else {
+        return no_job_message(interaction).await;
+    };
+
+    let config = GuildConfig::for_guild(&*state::db().await, message.guild_id.as_deref()).await?;
+    enqueue_deferred(interaction, &config, message, args).await
+}
+
+/// Bundle the files already attached to a result into one archive, without converting again
+async fn zip_results<J: Job>(interaction: &Interaction, job: Option<&J>) -> Result<(), BotError> {
+    let Some(job) = job else {
+        return job_unavailable(interaction).await;
+    };
+    if !is_author(interaction, job.message()) {
+        return not_author(interaction).await;
+    }
+
+    // The clicked message carries the results, with freshly signed URLs
+    let attachments = interaction
+        .message
+        .as_ref()
+        .and_then(|message| message.attachments.clone())
+        .unwrap_or_default();
+    if attachments.is_empty() {
+        return job_unavailable(interaction).await;
+    }
+    let Some(target) = InteractionTarget::from_interaction(interaction) else {
+        return no_job_message(interaction).await;
+    };
+
+    // Downloading the results can outlast the 3 second response window
+    api::defer_interaction(&interaction.id, &interaction.token).await?;
+
+    let mut files = Vec::new();
+    for attachment in attachments.download_all(4).await {
+        if let Some(error) = attachment.error {
+            MessageSend {
+                content: Some("❌ Couldn't download the results, try again later".to_string()),
+                message_reference: None,
+                components: None,
+                attachments: None,
+            }
+            .edit_original(&target)
+            .await?;
+            return Err(BotError::new("result_download_failed").push_str(error));
+        }
+        files.push((attachment.meta.filename, attachment.bytes.to_vec()));
+    }
+
+    MessageSend {
+        content: Some(format!("📦 {} file(s) in one archive", files.len())),
+        message_reference: None,
+        components: None,
+        attachments: Some(vec![(job.archive_name(), zip_files(&files)?)]),
+    }
+    .edit_original(&target)
+    .await?;
+
+    Ok(())
+}
+
+async fn job_unavailable(interaction: &Interaction) -> Result<(), BotError> {
+    api::respond_ephemeral(
+        &interaction.id,
+        &interaction.token,
+        "❌ This result is no longer available".to_string(),
+    )
+    .await
+}
+
+/// Only the member who started a job may re-run it from the result's controls
+fn is_author(interaction: &Interaction, job_message: &Message) -> bool {
+    interaction
+        .invoker()
+        .is_some_and(|user| user.id == job_message.author.id)
+}
+
+async fn not_author(interaction: &Interaction) -> Result<(), BotError> {
+    api::respond_ephemeral(
+        &interaction.id,
+        &interaction.token,
+        "❌ Only the person who started this job can use these controls".to_string(),
+    )
+    .await
+}
+
+/// The interaction came without a user or channel, so there is nothing to answer a job in
+async fn no_job_message(interaction: &Interaction) -> Result<(), BotError> {
+    api::respond_ephemeral(
+        &interaction.id,
+        &interaction.token,
+        "❌ Can't start a job from here, try the command again".to_string(),
+    )
+    .await
+}
+
+async fn open_blp_settings(interaction: &Interaction, job_id: &str) -> Result<(), BotError> {
+    let Some(job) = JobBlp::find_by_id(&*state::db().await, job_id).await? else {
+        return job_unavailable(interaction).await;
+    };
+    if !is_author(interaction, &job.message) {
+        return not_author(interaction).await;
+    }
+
+    let modal = ModalData {
+        custom_id: format!("blp:advanced:{}", job_id),
+        title: "BLP settings".to_string(),
+        components: vec![
+            ActionRow::new(vec![Component::text_input(
+                "quality",
+                "JPEG quality (1-100)",
+                "80",
+                Some(job.quality.to_string()),
+            )]),
+            ActionRow::new(vec![Component::text_input(
+                "mip_count",
+                "Mip levels (1-16, empty for all)",
+                "16",
+                job.mip_count.map(|count| count.to_string()),
+            )]),
+            ActionRow::new(vec![Component::text_input(
+                "alpha_depth",
+                "Alpha depth (0 or 8 bits, empty to keep)",
+                "8",
+                job.alpha_depth.map(|depth| depth.to_string()),
+            )]),
+        ],
+    };
+
+    api::show_modal(&interaction.id, &interaction.token, modal).await
+}
+
+async fn open_icon_settings(interaction: &Interaction, job_id: &str) -> Result<(), BotError> {
+    let Some(job) = JobIcon::find_by_id(&*state::db().await, job_id).await? else {
+        return job_unavailable(interaction).await;
+    };
+    if !is_author(interaction, &job.message) {
+        return not_author(interaction).await;
+    }
+
+    let modal = ModalData {
+        custom_id: format!("icon:advanced:{}", job_id),
+        title: "Icon settings".to_string(),
+        components: vec![
+            ActionRow::new(vec![Component::text_input(
+                "crop_x",
+                "Crop X offset (px, empty to center)",
+                "0",
+                job.crop_x.map(|x| x.to_string()),
+            )]),
+            ActionRow::new(vec![Component::text_input(
+                "crop_y",
+                "Crop Y offset (px, empty to center)",
+                "0",
+                job.crop_y.map(|y| y.to_string()),
+            )]),
+            ActionRow::new(vec![Component::text_input(
+                "output_path",
+                "Archive folder (empty for defaults)",
+                "war3mapImported\\",
+                job.output_path,
+            )]),
+        ],
+    };
+
+    api::show_modal(&interaction.id, &interaction.token, modal).await
+}
+
+/// Values of the BLP settings modal; empty inputs stay unset
+struct BlpSettings {
+    quality: Option<u8>,
+    mip_count: Option<u8>,
+    alpha_depth: Option<u8>,
+}
+
+/// Values of the icon settings modal; empty inputs stay unset
+struct IconSettings {
+    crop_x: Option<u32>,
+    crop_y: Option<u32>,
+    output_path: Option<String>,
+}
+
+fn parse_blp_settings(data: &InteractionData) -> Result<BlpSettings, &'static str> {
+    let quality = match data.text_value("quality") {
+        Some(value) => match value.parse::<u8>() {
+            Ok(quality) if (1..=100).contains(&quality) => Some(quality),
+            _ => return Err("quality must be a number from 1 to 100"),
+        },
+        None => None,
+    };
+
+    let mip_count = match data.text_value("mip_count") {
+        Some(value) => match value.parse::<u8>() {
+            Ok(count) if (1..=16).contains(&count) => Some(count),
+            _ => return Err("mip levels must be a number from 1 to 16"),
+        },
+        None => None,
+    };
+
+    let alpha_depth = match data.text_value("alpha_depth") {
+        Some(value) => match value.parse::<u8>() {
+            Ok(depth @ (0 | 8)) => Some(depth),
+            _ => return Err("alpha depth must be 0 or 8"),
+        },
+        None => None,
+    };
+
+    Ok(BlpSettings {
+        quality,
+        mip_count,
+        alpha_depth,
+    })
+}
+
+fn parse_icon_settings(data: &InteractionData) -> Result<IconSettings, &'static str> {
+    let offset = |name| match data.text_value(name) {
+        Some(value) => value
+            .parse::<u32>()
+            .map(Some)
+            .map_err(|_| "crop offsets must be whole numbers of pixels"),
+        None => Ok(None),
+    };
+
+    let crop_x = offset("crop_x")?;
+    let crop_y = offset("crop_y")?;
+
+    let output_path = data
+        .text_value("output_path")
+        .map(JobIcon::normalize_output_path);
+
+    Ok(IconSettings {
+        crop_x,
+        crop_y,
+        output_path,
+    })
+}
+
+async fn invalid_settings(interaction: &Interaction, message: &str) -> Result<(), BotError> {
+    api::respond_ephemeral(
+        &interaction.id,
+        &interaction.token,
+        format!("❌ Invalid settings: {}", message),
+    )
+    .await
+}
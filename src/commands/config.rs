@@ -0,0 +1,314 @@
+use crate::commands::{self, Command, CommandOption, SlashCommand};
+use crate::assets::FrameStyle;
+use crate::db::guild_config::{GuildConfig, Language, mention_list};
+use crate::discord::api;
+use crate::discord::discord::{Interaction, OptionType};
+use crate::discord::permissions::MANAGE_GUILD;
+use crate::error::BotError;
+use crate::state;
+
+pub struct Config;
+
+impl Command for Config {
+    const NAME: &'static str = "config";
+
+    fn definition() -> SlashCommand {
+        SlashCommand {
+            name: Self::NAME.to_string(),
+            command_type: 1,
+            description: "View or change the bot settings of this server".to_string(),
+            options: vec![
+                CommandOption::new(OptionType::SubCommand, "show", "Show the current settings"),
+                CommandOption::new(OptionType::SubCommand, "set", "Change the defaults for commands")
+                    .option(
+                        CommandOption::new(OptionType::Integer, "quality", "Default BLP quality (1-100)")
+                            .range(1, 100),
+                    )
+                    .option(
+                        CommandOption::new(
+                            OptionType::Integer,
+                            "threshold",
+                            "Default background removal threshold (0-255)",
+                        )
+                        .range(0, 255),
+                    )
+                    .option(CommandOption::new(
+                        OptionType::Boolean,
+                        "zip",
+                        "Bundle results into a ZIP archive by default",
+                    ))
+                    .option(
+                        CommandOption::new(
+                            OptionType::Integer,
+                            "max_attachments",
+                            "Images taken from one message (1-10)",
+                        )
+                        .range(1, GuildConfig::MAX_ATTACHMENTS as i64),
+                    )
+                    .option(
+                        CommandOption::new(OptionType::String, "frame_style", "Frame drawn over generated icons")
+                            .choice("Classic", FrameStyle::Classic.as_ref())
+                            .choice("None, for clients that draw their own", FrameStyle::None.as_ref()),
+                    )
+                    .option(
+                        CommandOption::new(OptionType::String, "language", "Language of the conversion replies")
+                            .choice("English", Language::En.as_ref())
+                            .choice("Русский", Language::Ru.as_ref()),
+                    ),
+                CommandOption::new(OptionType::SubCommand, "allow", "Answer in this channel, once one is allowed only listed channels are used")
+                    .option(
+                        CommandOption::new(OptionType::Channel, "channel", "Channel to allow")
+                            .required(),
+                    ),
+                CommandOption::new(OptionType::SubCommand, "disallow", "Remove a channel from the allowed list")
+                    .option(
+                        CommandOption::new(OptionType::Channel, "channel", "Channel to remove")
+                            .required(),
+                    ),
+                CommandOption::new(OptionType::SubCommand, "quota", "Change job limits for members, 0 turns a limit off")
+                    .option(quota_option("jobs_per_hour", "Jobs one member may start per hour"))
+                    .option(quota_option("guild_jobs_per_hour", "Jobs the whole server may start per hour"))
+                    .option(quota_option("megabytes_per_day", "Image megabytes one member may send per day"))
+                    .option(quota_option("pending_jobs", "Jobs one member may have queued at once")),
+                CommandOption::new(OptionType::SubCommandGroup, "access", "Channel and role rules for one command")
+                    .option(access_subcommand("allow", "Allow a channel or role, once one is allowed only listed ones can use it"))
+                    .option(access_subcommand("deny", "Deny a channel or role"))
+                    .option(
+                        CommandOption::new(OptionType::SubCommand, "clear", "Remove all rules of a command")
+                            .option(command_option()),
+                    ),
+                CommandOption::new(OptionType::SubCommand, "reset", "Restore the default settings"),
+            ],
+            // Server admins can hand the command to other roles in the integration settings
+            default_member_permissions: Some(MANAGE_GUILD.to_string()),
+        }
+    }
+
+    async fn handle(interaction: Interaction) -> Result<(), BotError> {
+        let Some(guild_id) = interaction.guild_id.clone() else {
+            return api::respond_ephemeral(
+                &interaction.id,
+                &interaction.token,
+                "❌ Settings belong to a server, use this command there".to_string(),
+            )
+            .await;
+        };
+
+        let Some(data) = &interaction.data else {
+            return Ok(());
+        };
+
+        let db = state::db().await;
+        let mut config = GuildConfig::get(&db, &guild_id).await?;
+
+        let header = match data.subcommand_path().as_slice() {
+            ["set"] => {
+                if let Some(quality) = data.get_integer("quality") {
+                    config.quality = quality.clamp(1, 100) as u8;
+                }
+                if let Some(threshold) = data.get_integer("threshold") {
+                    config.threshold = threshold.clamp(0, 255) as u8;
+                }
+                if let Some(zip) = data.get_bool("zip") {
+                    config.zip = zip;
+                }
+                if let Some(max) = data.get_integer("max_attachments") {
+                    config.max_attachments = max.clamp(1, GuildConfig::MAX_ATTACHMENTS as i64) as u8;
+                }
+                if let Some(style) = data.get_string("frame_style").and_then(|s| s.parse().ok()) {
+                    config.frame_style = style;
+                }
+                if let Some(language) = data.get_string("language").and_then(|s| s.parse().ok()) {
+                    config.language = language;
+                }
+                config.save(&db).await?;
+                "✅ Settings updated"
+            }
+            ["allow"] => {
+                if let Some(channel_id) = data.get_string("channel")
+                    && !config.allowed_channels.iter().any(|id| id == channel_id)
+                {
+                    config.allowed_channels.push(channel_id.to_string());
+                    config.save(&db).await?;
+                }
+                "✅ Channel allowed"
+            }
+            ["disallow"] => {
+                if let Some(channel_id) = data.get_string("channel") {
+                    config.allowed_channels.retain(|id| id != channel_id);
+                    config.save(&db).await?;
+                }
+                "✅ Channel removed"
+            }
+            ["quota"] => {
+                let quota = &mut config.quota;
+                for (name, limit) in [
+                    ("jobs_per_hour", &mut quota.jobs_per_hour),
+                    ("guild_jobs_per_hour", &mut quota.guild_jobs_per_hour),
+                    ("megabytes_per_day", &mut quota.megabytes_per_day),
+                    ("pending_jobs", &mut quota.pending_jobs),
+                ] {
+                    if let Some(value) = data.get_integer(name) {
+                        *limit = value.clamp(0, QUOTA_MAX) as u32;
+                    }
+                }
+                config.save(&db).await?;
+                "✅ Job limits updated"
+            }
+            ["access", action] => {
+                let Some(kind) = data.get_string("command").and_then(commands::kind_for_alias)
+                else {
+                    return api::respond_ephemeral(
+                        &interaction.id,
+                        &interaction.token,
+                        "❌ Unknown command, pick blp, png, rembg or icon".to_string(),
+                    )
+                    .await;
+                };
+                let channel_id = data.get_string("channel");
+                let role_id = data.get_string("role");
+
+                let header = match *action {
+                    "clear" => {
+                        config.access.retain(|rule| rule.kind != kind);
+                        "✅ Access rules cleared"
+                    }
+                    _ if channel_id.is_none() && role_id.is_none() => "⚠️ Pick a channel or a role",
+                    "allow" => {
+                        let rule = config.access_rule_mut(kind);
+                        move_id(channel_id, &mut rule.denied_channels, &mut rule.allowed_channels);
+                        move_id(role_id, &mut rule.denied_roles, &mut rule.allowed_roles);
+                        "✅ Access rules updated"
+                    }
+                    _ => {
+                        let rule = config.access_rule_mut(kind);
+                        move_id(channel_id, &mut rule.allowed_channels, &mut rule.denied_channels);
+                        move_id(role_id, &mut rule.allowed_roles, &mut rule.denied_roles);
+                        "✅ Access rules updated"
+                    }
+                };
+
+                config.access.retain(|rule| !rule.is_empty());
+                config.save(&db).await?;
+                header
+            }
+            ["reset"] => {
+                GuildConfig::reset(&db, &guild_id).await?;
+                config = GuildConfig::get(&db, &guild_id).await?;
+                "✅ Settings reset to defaults"
+            }
+            _ => "⚙️ **Server settings**",
+        };
+
+        api::respond_ephemeral(
+            &interaction.id,
+            &interaction.token,
+            format!("{}\n\n{}", header, describe(&config)),
+        )
+        .await
+    }
+}
+
+/// Upper bound for any quota option
+const QUOTA_MAX: i64 = 100_000;
+
+fn quota_option(name: &str, description: &str) -> CommandOption {
+    CommandOption::new(OptionType::Integer, name, description).range(0, QUOTA_MAX)
+}
+
+/// Quota value for display, 0 means the limit is off
+fn describe_limit(value: u32) -> String {
+    if value == 0 {
+        "no limit".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// `allow`/`deny` take the command plus a channel, a role, or both
+fn access_subcommand(name: &str, description: &str) -> CommandOption {
+    CommandOption::new(OptionType::SubCommand, name, description)
+        .option(command_option())
+        .option(CommandOption::new(OptionType::Channel, "channel", "Channel the rule applies to"))
+        .option(CommandOption::new(OptionType::Role, "role", "Role the rule applies to"))
+}
+
+fn command_option() -> CommandOption {
+    CommandOption::new(OptionType::String, "command", "Command the rule is for")
+        .required()
+        .choice("blp", "blp")
+        .choice("png", "png")
+        .choice("rembg", "rembg")
+        .choice("icon", "icon")
+}
+
+/// Put an id on one list and take it off the opposite one
+fn move_id(id: Option<&str>, from: &mut Vec<String>, to: &mut Vec<String>) {
+    let Some(id) = id else {
+        return;
+    };
+
+    from.retain(|existing| existing != id);
+    if !to.iter().any(|existing| existing == id) {
+        to.push(id.to_string());
+    }
+}
+
+fn describe(config: &GuildConfig) -> String {
+    let channels = if config.allowed_channels.is_empty() {
+        "all channels".to_string()
+    } else {
+        mention_list(&config.allowed_channels, "<#", ">")
+    };
+
+    let mut rules: Vec<String> = config
+        .access
+        .iter()
+        .map(|rule| {
+            let mut parts = Vec::new();
+            if !rule.allowed_channels.is_empty() {
+                parts.push(format!("only in {}", mention_list(&rule.allowed_channels, "<#", ">")));
+            }
+            if !rule.denied_channels.is_empty() {
+                parts.push(format!("not in {}", mention_list(&rule.denied_channels, "<#", ">")));
+            }
+            if !rule.allowed_roles.is_empty() {
+                parts.push(format!("only for {}", mention_list(&rule.allowed_roles, "<@&", ">")));
+            }
+            if !rule.denied_roles.is_empty() {
+                parts.push(format!("not for {}", mention_list(&rule.denied_roles, "<@&", ">")));
+            }
+            format!("  `{}`: {}", rule.kind.name(), parts.join("; "))
+        })
+        .collect();
+    if rules.is_empty() {
+        rules.push("  none".to_string());
+    }
+
+    format!(
+        "• Default BLP quality: {}\n\
+         • Default background threshold: {}\n\
+         • ZIP by default: {}\n\
+         • Allowed channels: {}\n\
+         • Images per message: {}\n\
+         • Icon frame style: {}\n\
+         • Reply language: {}\n\
+         • Jobs per member per hour: {}\n\
+         • Jobs per server per hour: {}\n\
+         • Megabytes per member per day: {}\n\
+         • Queued jobs per member: {}\n\
+         • Access rules:\n{}",
+        config.quality,
+        config.threshold,
+        if config.zip { "yes" } else { "no" },
+        channels,
+        config.max_attachments,
+        config.frame_style.as_ref(),
+        config.language.as_ref(),
+        describe_limit(config.quota.jobs_per_hour),
+        describe_limit(config.quota.guild_jobs_per_hour),
+        describe_limit(config.quota.megabytes_per_day),
+        describe_limit(config.quota.pending_jobs),
+        rules.join("\n"),
+    )
+}
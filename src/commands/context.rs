@@ -0,0 +1,75 @@
+//! Message context menu commands ("Apps" on right click) that run on the images of that message
+
+use crate::commands::{Command, SlashCommand, enqueue_target_message_job};
+use crate::discord::discord::Interaction;
+use crate::discord::message::handle::CommandKind;
+use crate::error::BotError;
+
+/// https://discord.com/developers/docs/interactions/application-commands#message-commands
+const MESSAGE_COMMAND: u8 = 3;
+
+fn message_command(name: &str) -> SlashCommand {
+    SlashCommand {
+        name: name.to_string(),
+        command_type: MESSAGE_COMMAND,
+        description: String::new(), // Must be empty for context menu commands
+        options: Vec::new(),
+        default_member_permissions: None,
+    }
+}
+
+pub struct ConvertToBlp;
+
+impl Command for ConvertToBlp {
+    const NAME: &'static str = "Convert to BLP";
+
+    fn definition() -> SlashCommand {
+        message_command(Self::NAME)
+    }
+
+    async fn handle(interaction: Interaction) -> Result<(), BotError> {
+        enqueue_target_message_job(&interaction, CommandKind::Blp).await
+    }
+}
+
+pub struct ConvertToPng;
+
+impl Command for ConvertToPng {
+    const NAME: &'static str = "Convert to PNG";
+
+    fn definition() -> SlashCommand {
+        message_command(Self::NAME)
+    }
+
+    async fn handle(interaction: Interaction) -> Result<(), BotError> {
+        enqueue_target_message_job(&interaction, CommandKind::Png).await
+    }
+}
+
+pub struct MakeIcons;
+
+impl Command for MakeIcons {
+    const NAME: &'static str = "Make icons";
+
+    fn definition() -> SlashCommand {
+        message_command(Self::NAME)
+    }
+
+    async fn handle(interaction: Interaction) -> Result<(), BotError> {
+        enqueue_target_message_job(&interaction, CommandKind::Icon).await
+    }
+}
+
+pub struct RemoveBackground;
+
+impl Command for RemoveBackground {
+    const NAME: &'static str = "Remove background";
+
+    fn definition() -> SlashCommand {
+        message_command(Self::NAME)
+    }
+
+    async fn handle(interaction: Interaction) -> Result<(), BotError> {
+        enqueue_target_message_job(&interaction, CommandKind::Rembg).await
+    }
+}
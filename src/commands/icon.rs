@@ -1,23 +1,67 @@
-use crate::commands::{Command, SlashCommand};
+use crate::assets::BORDER_VARIANTS;
+use crate::commands::{
+    Command, CommandOption, SlashCommand, check_bot_permissions, enqueue_attachment_job,
+    matching_choices,
+};
+use crate::discord::message::handle::CommandKind;
 use crate::discord::api;
-use crate::discord::discord::Interaction;
+use crate::discord::discord::{CommandOptionChoice, Interaction, OptionType};
 use crate::error::BotError;
 use crate::state;
+use crate::workers::icon::job::JobIcon;
 
 pub struct Icon;
 
 impl Command for Icon {
+    const NAME: &'static str = "icon";
+    const ALIASES: &'static [&'static str] = &["icon"];
+    const KIND: Option<CommandKind> = Some(CommandKind::Icon);
+
     fn definition() -> SlashCommand {
         SlashCommand {
-            name: "icon".to_string(),
+            name: Self::NAME.to_string(),
             command_type: 1,
-            description: "Information about icon conversion".to_string(),
+            description: "Generate Warcraft III icons from an image or show usage information".to_string(),
+            options: vec![
+                CommandOption::new(OptionType::Attachment, "image", "Image to turn into icons"),
+                CommandOption::new(OptionType::String, "frame", "Render only this border variant, e.g. BTN")
+                    .autocomplete(),
+                CommandOption::new(OptionType::String, "path", "Archive folder, e.g. war3mapImported\\")
+                    .autocomplete(),
+            ],
+            default_member_permissions: None,
+        }
+    }
+
+    async fn autocomplete(interaction: Interaction) -> Result<Vec<CommandOptionChoice>, BotError> {
+        let Some(focused) = interaction.data.as_ref().and_then(|d| d.focused_option()) else {
+            return Ok(Vec::new());
+        };
+        let typed = focused.value.as_ref().and_then(|v| v.as_str()).unwrap_or_default();
+
+        match focused.name.as_str() {
+            "frame" => Ok(matching_choices(
+                BORDER_VARIANTS.iter().map(|variant| variant.to_string()),
+                typed,
+            )),
+            "path" => {
+                // Folders this user archived icons into before
+                let Some(user) = interaction.invoker() else {
+                    return Ok(Vec::new());
+                };
+                let paths = JobIcon::recent_output_paths(&*state::db().await, &user.id).await?;
+                Ok(matching_choices(paths, typed))
+            }
+            _ => Ok(Vec::new()),
         }
     }
 
     async fn handle(interaction: Interaction) -> Result<(), BotError> {
-        let client = state::client().await;
-        let token = state::token().await;
+        // With an image attached the command does the work, otherwise it shows help
+        if enqueue_attachment_job(&interaction, CommandKind::Icon).await? {
+            return Ok(());
+        }
+
         let db = state::db().await;
 
         // Check if icon is available
@@ -25,7 +69,7 @@ impl Command for Icon {
 
         // Check bot permissions in this channel
         let permissions_info = if let Some(channel_id) = &interaction.channel_id {
-            check_bot_permissions(&client, &token, channel_id).await
+            check_bot_permissions(channel_id).await
         } else {
             "⚠️ Unable to determine channel permissions".to_string()
         };
@@ -80,7 +124,9 @@ icons.zip/
 │       └── DISPAS[filename].blp
 ```\n\n\
 **Usage:**\n\
-Upload one or more images and use `/icon` command\n\n\
+Upload one or more images and use `/icon` command\n\
+Or attach an image to the slash command: `/icon image:<file> frame:BTN path:war3mapImported\\`\n\
+Mentions take the same options: `@Raft icon frame:BTN path:war3mapImported\\`\n\n\
 **Output:**\n\
 • `icon_collage.png` - Preview collage showing all icon variants\n\
 • `icons.zip` - ZIP archive containing BLP icons and preview collage\n\n\
@@ -97,8 +143,6 @@ Upload one or more images and use `/icon` command\n\n\
         );
 
         api::respond_to_interaction(
-            &client,
-            &token,
             &interaction.id,
             &interaction.token,
             info_text,
@@ -108,83 +152,3 @@ Upload one or more images and use `/icon` command\n\n\
         Ok(())
     }
 }
-
-async fn check_bot_permissions(
-    client: &reqwest::Client,
-    token: &str,
-    channel_id: &str,
-) -> String {
-    let bot_user_id = state::bot_user_id().await;
-    if bot_user_id.is_empty() {
-        return "⚠️ Unable to check bot permissions".to_string();
-    }
-
-    match get_channel_permissions(client, token, channel_id, &bot_user_id).await {
-        Ok(permissions) => {
-            let can_send_messages = permissions & (1 << 11) != 0; // SEND_MESSAGES
-            let can_attach_files = permissions & (1 << 15) != 0; // ATTACH_FILES
-
-            if can_send_messages && can_attach_files {
-                "✅ Bot has required permissions in this channel".to_string()
-            } else {
-                "⚠️ Bot may not have permission to send messages or attachments".to_string()
-            }
-        }
-        Err(_) => "⚠️ Unable to check bot permissions".to_string(),
-    }
-}
-
-/// Get channel permissions for bot user
-async fn get_channel_permissions(
-    client: &reqwest::Client,
-    token: &str,
-    channel_id: &str,
-    _user_id: &str,
-) -> Result<u64, BotError> {
-    // Apply rate limiting before Discord API request
-    let limiter = crate::state::rate_limiter().await;
-    limiter.acquire().await;
-
-    let response = client
-        .get(&format!(
-            "https://discord.com/api/v10/channels/{}",
-            channel_id
-        ))
-        .header("Authorization", format!("Bot {}", token))
-        .send()
-        .await?;
-
-    // Store rate limits from response headers
-    let _ = crate::db::rate_limits::RateLimit::update_from_headers(
-        &*crate::state::db().await,
-        format!("/channels/{}", channel_id),
-        response.headers(),
-    )
-    .await;
-
-    // Handle 403 Forbidden - bot is not in this server
-    if response.status() == reqwest::StatusCode::FORBIDDEN {
-        return Err(crate::error::BotError::new("bot_not_in_server"));
-    }
-
-    if !response.status().is_success() {
-        return Err(crate::error::BotError::new("channel_fetch_failed")
-            .push_str(format!("Status: {}", response.status())));
-    }
-
-    let channel_data: serde_json::Value = response.json().await?;
-
-    // For DMs, assume we have all permissions
-    if channel_data["type"].as_u64() == Some(1) {
-        return Ok(0x8000 | 0x800 | 0x400 | 0x10000); // Basic DM permissions
-    }
-
-    // For guild channels, we would need to calculate permissions based on:
-    // - Guild member roles
-    // - Channel permission overwrites
-    // This is complex, so for now return a basic check
-
-    // TODO: Implement full permission calculation
-    // For now, assume we have permissions (this should be improved)
-    Ok(0x8000 | 0x800 | 0x400 | 0x10000)
-}
\ No newline at end of file
@@ -1,12 +1,29 @@
 mod ahoy;
 mod blp;
+pub mod components;
+mod config;
+mod context;
 mod icon;
 mod png;
 mod rembg;
 
+use crate::db::guild_config::GuildConfig;
 use crate::error::{BotError};
-use crate::discord::discord::Interaction;
+use crate::discord::api;
+use crate::discord::discord::{
+    CommandOptionChoice, GuildMember, Interaction, InteractionTarget, OptionType,
+};
+use crate::discord::message::attachment::Attachment;
+use crate::discord::permissions;
+use crate::discord::message::handle::{
+    CommandArgs, CommandKind, enqueue_job, interaction_command_args,
+};
+use crate::discord::message::message::{Message, User};
+use crate::discord::message::send::MessageSend;
+use crate::state;
+use proc_macros::command_registry;
 use serde::Serialize;
+use serde_json::Value;
 
 #[derive(Debug, Serialize)]
 pub struct SlashCommand {
@@ -14,45 +31,359 @@ pub struct SlashCommand {
     #[serde(rename = "type")]
     pub command_type: u8,
     pub description: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<CommandOption>,
+    /// Permission bit set a member needs to see the command; server admins can override it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_member_permissions: Option<String>,
+}
+
+/// https://discord.com/developers/docs/interactions/application-commands#application-command-object-application-command-option-structure
+#[derive(Debug, Serialize)]
+pub struct CommandOption {
+    #[serde(rename = "type")]
+    pub option_type: OptionType,
+    pub name: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub required: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<CommandOptionChoice>,
+    /// Nested options of a subcommand or subcommand group
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<CommandOption>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_value: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_value: Option<i64>,
+    /// Choices come from `Command::autocomplete` while the user types
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub autocomplete: bool,
+}
+
+impl CommandOption {
+    pub fn new(option_type: OptionType, name: &str, description: &str) -> Self {
+        Self {
+            option_type,
+            name: name.to_string(),
+            description: description.to_string(),
+            required: false,
+            choices: Vec::new(),
+            options: Vec::new(),
+            min_value: None,
+            max_value: None,
+            autocomplete: false,
+        }
+    }
+
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Inclusive bounds of an integer option
+    pub fn range(mut self, min: i64, max: i64) -> Self {
+        self.min_value = Some(min);
+        self.max_value = Some(max);
+        self
+    }
+
+    pub fn autocomplete(mut self) -> Self {
+        self.autocomplete = true;
+        self
+    }
+
+    pub fn choice(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.choices.push(CommandOptionChoice {
+            name: name.to_string(),
+            value: value.into(),
+        });
+        self
+    }
+
+    /// Add a nested option to a subcommand or subcommand group
+    pub fn option(mut self, option: CommandOption) -> Self {
+        self.options.push(option);
+        self
+    }
+
+    /// Whether an integer lies within the option's bounds
+    pub fn accepts(&self, value: i64) -> bool {
+        self.min_value.is_none_or(|min| value >= min) && self.max_value.is_none_or(|max| value <= max)
+    }
+
+    /// Value of the option written as `name:value` in a mention, checked like Discord checks slash input
+    pub fn mention_value(&self, raw: &str) -> Option<Value> {
+        let value = match self.option_type {
+            OptionType::Boolean => match raw.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Value::Bool(true),
+                "false" | "no" | "off" | "0" => Value::Bool(false),
+                _ => return None,
+            },
+            OptionType::Integer => Value::from(raw.parse::<i64>().ok().filter(|n| self.accepts(*n))?),
+            OptionType::String => Value::String(raw.to_string()),
+            // Attachments come with the message, the rest has no mention form
+            _ => return None,
+        };
+
+        let allowed = self.choices.is_empty() || self.choices.iter().any(|c| c.value == value);
+        allowed.then_some(value)
+    }
 }
 
 /// Trait for Discord slash commands
 pub trait Command {
+    /// Name Discord sends back in the interaction
+    const NAME: &'static str;
+
+    /// Keywords that start this command's job from a mention, e.g. `@Raft bg`
+    const ALIASES: &'static [&'static str] = &[];
+
+    /// Job queued for a mention with one of `ALIASES`
+    const KIND: Option<CommandKind> = None;
+
     /// Get command definition for registration
     fn definition() -> SlashCommand;
     
     /// Handle command execution
     fn handle(interaction: Interaction) -> impl std::future::Future<Output = Result<(), BotError>> + Send;
+
+    /// Suggest choices for the focused autocomplete option
+    fn autocomplete(
+        _interaction: Interaction,
+    ) -> impl std::future::Future<Output = Result<Vec<CommandOptionChoice>, BotError>> + Send {
+        async { Ok(Vec::new()) }
+    }
+}
+
+/// Discord shows at most 25 autocomplete choices
+const MAX_CHOICES: usize = 25;
+
+/// Choices whose value contains the typed text, case-insensitively
+pub fn matching_choices(
+    values: impl IntoIterator<Item = String>,
+    typed: &str,
+) -> Vec<CommandOptionChoice> {
+    let typed = typed.to_lowercase();
+
+    values
+        .into_iter()
+        .filter(|value| value.to_lowercase().contains(&typed))
+        .take(MAX_CHOICES)
+        .map(|value| CommandOptionChoice {
+            name: value.clone(),
+            value: Value::String(value),
+        })
+        .collect()
 }
 
-/// Get all registered commands for Discord API registration
-pub fn all_commands() -> Vec<SlashCommand> {
-    vec![
-        ahoy::Ahoy::definition(),
-        blp::Blp::definition(),
-        icon::Icon::definition(),
-        png::Png::definition(),
-        rembg::Rembg::definition(),
-    ]
+// The only place a command is listed: registration, routing and mention aliases come from here
+command_registry! {
+    ahoy::Ahoy,
+    blp::Blp,
+    config::Config,
+    icon::Icon,
+    png::Png,
+    rembg::Rembg,
+    context::ConvertToBlp,
+    context::ConvertToPng,
+    context::MakeIcons,
+    context::RemoveBackground,
 }
 
 /// Route interaction to appropriate command handler
 pub async fn handle_interaction(interaction: Interaction) -> Result<(), BotError> {
-    if interaction.interaction_type != 2 {
-        // Not an application command
-        return Ok(());
+    match interaction.interaction_type {
+        2 => {} // APPLICATION_COMMAND, routed below
+        3 => return components::handle_component(interaction).await, // MESSAGE_COMPONENT
+        4 => return handle_autocomplete(interaction).await, // APPLICATION_COMMAND_AUTOCOMPLETE
+        5 => return components::handle_modal_submit(interaction).await, // MODAL_SUBMIT
+        _ => return Ok(()),
     }
 
     let Some(data) = &interaction.data else {
         return Ok(());
     };
 
-    match data.name.as_str() {
-        "ahoy" => ahoy::Ahoy::handle(interaction).await,
-        "blp" => blp::Blp::handle(interaction).await,
-        "icon" => icon::Icon::handle(interaction).await,
-        "png" => png::Png::handle(interaction).await,
-        "rembg" => rembg::Rembg::handle(interaction).await,
-        _ => Ok(()), // Unknown command, ignore
+    let name = data.name.clone();
+    dispatch_command(&name, interaction).await
+}
+
+/// Answer an autocomplete interaction with the command's suggestions
+async fn handle_autocomplete(interaction: Interaction) -> Result<(), BotError> {
+    let Some(name) = interaction.data.as_ref().map(|data| data.name.clone()) else {
+        return Ok(());
+    };
+
+    let interaction_id = interaction.id.clone();
+    let interaction_token = interaction.token.clone();
+
+    let mut choices = dispatch_autocomplete(&name, interaction).await?;
+    choices.truncate(MAX_CHOICES);
+
+    api::respond_autocomplete(&interaction_id, &interaction_token, choices).await
+}
+
+/// Check bot permissions in the channel and return formatted status
+pub async fn check_bot_permissions(channel_id: &str) -> String {
+    match permissions::bot_channel_permissions(channel_id).await {
+        Ok(permissions) => {
+            let checks = [
+                ("View Channel", permissions::VIEW_CHANNEL),
+                ("Send Messages", permissions::SEND_MESSAGES),
+                ("Attach Files", permissions::ATTACH_FILES),
+                ("Read Message History", permissions::READ_MESSAGE_HISTORY),
+            ];
+
+            let status: Vec<String> = checks
+                .iter()
+                .map(|(name, flag)| {
+                    format!("• {}: {}", name, if permissions & flag != 0 { "✅" } else { "❌" })
+                })
+                .collect();
+
+            let header = if permissions & permissions::REQUIRED == permissions::REQUIRED {
+                "✅ All required permissions available"
+            } else {
+                "⚠️ Some permissions missing"
+            };
+
+            format!("{}\n{}", header, status.join("\n"))
+        }
+        Err(e) if e.key == "bot_not_in_server" => {
+            let invite_url = state::get_invite_url().await;
+            if !invite_url.is_empty() {
+                format!(
+                    "ℹ️ **Permissions:** Bot is not in this server\n\n[Click here to invite the bot]({})",
+                    invite_url
+                )
+            } else {
+                "ℹ️ **Permissions:** Bot needs to be invited to this server".to_string()
+            }
+        }
+        Err(_) => "⚠️ Unable to check permissions (you can still use the bot)".to_string(),
+    }
+}
+
+/// Enqueue a job when the slash command came with an `image` attachment.
+///
+/// Returns `false` when there is no image, so the command can show its help instead.
+pub async fn enqueue_attachment_job(
+    interaction: &Interaction,
+    kind: CommandKind,
+) -> Result<bool, BotError> {
+    let Some(attachment) = interaction
+        .data
+        .as_ref()
+        .and_then(|data| data.get_attachment("image"))
+    else {
+        return Ok(false);
+    };
+
+    enqueue_interaction_job(interaction, kind, vec![attachment.clone()]).await
+}
+
+/// Enqueue a job for the attachments of the message a context menu command was used on
+pub async fn enqueue_target_message_job(
+    interaction: &Interaction,
+    kind: CommandKind,
+) -> Result<(), BotError> {
+    let attachments = interaction
+        .data
+        .as_ref()
+        .and_then(|data| data.target_message())
+        .and_then(|message| message.attachments.clone())
+        .unwrap_or_default();
+
+    if attachments.is_empty() {
+        return api::respond_ephemeral(
+            &interaction.id,
+            &interaction.token,
+            "❌ This message has no attachments".to_string(),
+        )
+        .await;
+    }
+
+    enqueue_interaction_job(interaction, kind, attachments).await?;
+    Ok(())
+}
+
+/// Defer the interaction and queue a job that answers through it
+async fn enqueue_interaction_job(
+    interaction: &Interaction,
+    kind: CommandKind,
+    attachments: Vec<Attachment>,
+) -> Result<bool, BotError> {
+    let Some(data) = &interaction.data else {
+        return Ok(false);
+    };
+
+    let Some(message) = interaction_job_message(interaction, attachments) else {
+        return Ok(false);
+    };
+
+    let config = GuildConfig::for_guild(&*state::db().await, message.guild_id.as_deref()).await?;
+    let args = interaction_command_args(kind, data, config.command_defaults());
+    enqueue_deferred(interaction, &config, message, args).await?;
+
+    Ok(true)
+}
+
+/// Defer the interaction and queue the job, whose worker fills in the response
+///
+/// The guild's channel and access settings are checked first, see [`GuildConfig::admit`].
+/// A job that fails to queue replaces the "thinking…" response with an error instead.
+pub(crate) async fn enqueue_deferred(
+    interaction: &Interaction,
+    config: &GuildConfig,
+    mut message: Message,
+    args: CommandArgs,
+) -> Result<(), BotError> {
+    if let Some(refusal) = config.admit(args.kind, &mut message) {
+        return api::respond_ephemeral(&interaction.id, &interaction.token, refusal).await;
     }
+
+    // Conversions outlive the 3 second response window
+    api::defer_interaction(&interaction.id, &interaction.token).await?;
+
+    let target = InteractionTarget::from_interaction(interaction);
+    let result = enqueue_job(message, args, target.clone()).await;
+
+    if let (Err(e), Some(target)) = (&result, &target) {
+        let notice = MessageSend {
+            content: Some(format!("❌ Couldn't queue the job (`{}`), try again later", e.key)),
+            message_reference: None,
+            components: None,
+            attachments: None,
+        };
+        let _ = notice.edit_original(target).await;
+    }
+
+    result
+}
+
+/// Build the job message for an interaction that brings attachments along.
+///
+/// The message has no id, workers answer through the deferred interaction response instead.
+fn interaction_job_message(
+    interaction: &Interaction,
+    attachments: Vec<Attachment>,
+) -> Option<Message> {
+    Some(Message {
+        id: String::new(),
+        author: User {
+            id: interaction.invoker()?.id.clone(),
+            bot: None,
+        },
+        channel_id: interaction.channel_id.clone()?,
+        guild_id: interaction.guild_id.clone(),
+        member: interaction.member.as_ref().map(|member| GuildMember {
+            roles: member.roles.clone(),
+        }),
+        content: String::new(),
+        attachments,
+        mentions: Vec::new(),
+        message_reference: None,
+        webhook_id: None,
+    })
 }
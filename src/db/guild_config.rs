@@ -0,0 +1,315 @@
+use bson::serde_helpers::datetime;
+use chrono::{DateTime, Utc};
+use mongodb::{Collection, bson::doc};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use strum::{AsRefStr, EnumString};
+use crate::assets::FrameStyle;
+use crate::discord::cache;
+use crate::discord::discord::Channel;
+use crate::discord::message::handle::{CommandArgs, CommandKind};
+use crate::discord::message::message::Message;
+use crate::error::BotError;
+
+/// Per-guild defaults, changed by server managers through `/config`
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GuildConfig {
+    #[serde(rename = "_id")]
+    pub guild_id: String,
+
+    /// BLP quality when a command doesn't give one
+    pub quality: u8,
+
+    /// Background removal threshold when a command doesn't give one
+    pub threshold: u8,
+
+    /// Bundle results into a ZIP archive unless a command says otherwise
+    pub zip: bool,
+
+    /// Channels the bot answers in; empty allows every channel
+    pub allowed_channels: Vec<String>,
+
+    /// Attachments taken from one message, the rest are ignored
+    pub max_attachments: u8,
+
+    /// Frame drawn over generated icons
+    pub frame_style: FrameStyle,
+
+    /// Language of the job replies
+    pub language: Language,
+
+    /// Channel and role restrictions per command
+    pub access: Vec<AccessRule>,
+
+    /// Job limits for members; admins are exempt
+    pub quota: QuotaLimits,
+
+    #[serde_as(as = "Option<datetime::FromChrono04DateTime>")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Default for GuildConfig {
+    fn default() -> Self {
+        let args = CommandArgs::default();
+
+        Self {
+            guild_id: String::new(),
+            quality: args.quality,
+            threshold: args.threshold,
+            zip: args.zip,
+            allowed_channels: Vec::new(),
+            max_attachments: Self::MAX_ATTACHMENTS,
+            frame_style: args.frame_style,
+            language: Language::default(),
+            access: Vec::new(),
+            quota: QuotaLimits::default(),
+            updated_at: None,
+        }
+    }
+}
+
+impl GuildConfig {
+    const COLLECTION_NAME: &'static str = "discord_guild_config";
+
+    /// Discord allows at most 10 attachments per message
+    pub const MAX_ATTACHMENTS: u8 = 10;
+
+    /// Settings of a guild, defaults when it never changed them
+    pub async fn get(db: &mongodb::Database, guild_id: &str) -> Result<GuildConfig, BotError> {
+        let collection: Collection<GuildConfig> = db.collection(Self::COLLECTION_NAME);
+
+        let config = collection.find_one(doc! { "_id": guild_id }).await?;
+
+        Ok(config.unwrap_or_else(|| GuildConfig {
+            guild_id: guild_id.to_string(),
+            ..Default::default()
+        }))
+    }
+
+    /// Settings for a message or interaction; DMs always use the defaults
+    pub async fn for_guild(
+        db: &mongodb::Database,
+        guild_id: Option<&str>,
+    ) -> Result<GuildConfig, BotError> {
+        match guild_id {
+            Some(guild_id) => Self::get(db, guild_id).await,
+            None => Ok(GuildConfig::default()),
+        }
+    }
+
+    pub async fn save(&mut self, db: &mongodb::Database) -> Result<(), BotError> {
+        let collection: Collection<GuildConfig> = db.collection(Self::COLLECTION_NAME);
+
+        self.updated_at = Some(Utc::now());
+
+        collection
+            .replace_one(doc! { "_id": &self.guild_id }, &*self)
+            .upsert(true)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Forget the guild's settings, so it is back on the defaults
+    pub async fn reset(db: &mongodb::Database, guild_id: &str) -> Result<(), BotError> {
+        let collection: Collection<GuildConfig> = db.collection(Self::COLLECTION_NAME);
+
+        collection.delete_one(doc! { "_id": guild_id }).await?;
+
+        Ok(())
+    }
+
+    pub fn allows_channel(&self, channel_id: &str) -> bool {
+        self.allowed_channels.is_empty() || in_channels(&self.allowed_channels, channel_id)
+    }
+
+    pub fn access_rule(&self, kind: CommandKind) -> Option<&AccessRule> {
+        self.access.iter().find(|rule| rule.kind == kind)
+    }
+
+    /// Rule for a command, created empty when the guild has none yet
+    pub fn access_rule_mut(&mut self, kind: CommandKind) -> &mut AccessRule {
+        let index = match self.access.iter().position(|rule| rule.kind == kind) {
+            Some(index) => index,
+            None => {
+                self.access.push(AccessRule::new(kind));
+                self.access.len() - 1
+            }
+        };
+        &mut self.access[index]
+    }
+
+    /// Why this member may not run the command here, or None when they may
+    pub fn access_refusal(
+        &self,
+        kind: CommandKind,
+        channel_id: &str,
+        member_roles: &[String],
+    ) -> Option<String> {
+        let rule = self.access_rule(kind)?;
+        let name = kind.name();
+
+        if in_channels(&rule.denied_channels, channel_id) {
+            return Some(format!("🚫 `{}` is turned off in this channel", name));
+        }
+
+        if !rule.allowed_channels.is_empty() && !in_channels(&rule.allowed_channels, channel_id) {
+            return Some(format!(
+                "🚫 `{}` can only be used in {}",
+                name,
+                mention_list(&rule.allowed_channels, "<#", ">")
+            ));
+        }
+
+        if member_roles.iter().any(|role| rule.denied_roles.contains(role)) {
+            return Some(format!("🚫 `{}` is not available for your role", name));
+        }
+
+        if !rule.allowed_roles.is_empty()
+            && !member_roles.iter().any(|role| rule.allowed_roles.contains(role))
+        {
+            return Some(format!(
+                "🚫 `{}` needs one of these roles: {}",
+                name,
+                mention_list(&rule.allowed_roles, "<@&", ">")
+            ));
+        }
+
+        None
+    }
+
+    /// Why the job can't be queued from this message, or None after trimming its attachments
+    ///
+    /// The one gate for mentions, slash commands and result buttons alike.
+    pub fn admit(&self, kind: CommandKind, message: &mut Message) -> Option<String> {
+        if !self.allows_channel(&message.channel_id) {
+            return Some(
+                "❌ The bot is turned off in this channel by the server settings".to_string(),
+            );
+        }
+
+        let member_roles = message.member.as_ref().map(|member| member.roles.as_slice());
+        if let Some(refusal) =
+            self.access_refusal(kind, &message.channel_id, member_roles.unwrap_or_default())
+        {
+            return Some(refusal);
+        }
+
+        message.attachments.truncate(self.max_attachments as usize);
+        None
+    }
+
+    /// Command arguments to start from before the command's own options are applied
+    pub fn command_defaults(&self) -> CommandArgs {
+        CommandArgs {
+            quality: self.quality,
+            threshold: self.threshold,
+            zip: self.zip,
+            frame_style: self.frame_style,
+            ..Default::default()
+        }
+    }
+}
+
+/// Language the workers answer in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    Ru,
+}
+
+impl Language {
+    /// The text for this language
+    pub fn pick<T>(self, en: T, ru: T) -> T {
+        match self {
+            Self::En => en,
+            Self::Ru => ru,
+        }
+    }
+}
+
+/// Where and by whom one command may be used; empty lists don't restrict anything
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccessRule {
+    pub kind: CommandKind,
+    #[serde(default)]
+    pub allowed_channels: Vec<String>,
+    #[serde(default)]
+    pub denied_channels: Vec<String>,
+    #[serde(default)]
+    pub allowed_roles: Vec<String>,
+    #[serde(default)]
+    pub denied_roles: Vec<String>,
+}
+
+impl AccessRule {
+    pub fn new(kind: CommandKind) -> Self {
+        Self {
+            kind,
+            allowed_channels: Vec::new(),
+            denied_channels: Vec::new(),
+            allowed_roles: Vec::new(),
+            denied_roles: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allowed_channels.is_empty()
+            && self.denied_channels.is_empty()
+            && self.allowed_roles.is_empty()
+            && self.denied_roles.is_empty()
+    }
+}
+
+/// Limits on how much one user, or the whole guild, may queue; 0 turns a limit off
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct QuotaLimits {
+    /// Jobs one user may start per hour
+    pub jobs_per_hour: u32,
+
+    /// Jobs the whole guild may start per hour
+    pub guild_jobs_per_hour: u32,
+
+    /// Attachment megabytes one user may send per day
+    pub megabytes_per_day: u32,
+
+    /// Jobs one user may have waiting or running at once
+    pub pending_jobs: u32,
+}
+
+impl Default for QuotaLimits {
+    fn default() -> Self {
+        Self {
+            jobs_per_hour: 30,
+            guild_jobs_per_hour: 300,
+            megabytes_per_day: 500,
+            pending_jobs: 5,
+        }
+    }
+}
+
+/// Whether the channel, or the channel a thread was started in, is listed
+fn in_channels(channels: &[String], channel_id: &str) -> bool {
+    let parent_id = cache::channel(channel_id)
+        .filter(Channel::is_thread)
+        .and_then(|channel| channel.parent_id);
+
+    channels
+        .iter()
+        .any(|id| id == channel_id || Some(id) == parent_id.as_ref())
+}
+
+/// Format ids as Discord mentions, e.g. `<#id>` for channels
+pub fn mention_list(ids: &[String], prefix: &str, suffix: &str) -> String {
+    ids.iter()
+        .map(|id| format!("{}{}{}", prefix, id, suffix))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
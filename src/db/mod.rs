@@ -1,3 +1,4 @@
+pub mod guild_config;
 pub mod heartbeat;
 pub mod mongo;
 pub mod rate_limits;
@@ -63,8 +63,7 @@ impl RateLimit {
             .get("x-ratelimit-reset")
             .and_then(|v| v.to_str().ok())
             .and_then(|v| v.parse::<f64>().ok())
-            .map(|ts| Utc.timestamp_opt(ts as i64, 0).single())
-            .flatten()
+            .and_then(|ts| Utc.timestamp_opt(ts as i64, 0).single())
             .unwrap_or_else(Utc::now);
 
         let reset_after = headers
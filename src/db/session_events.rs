@@ -8,11 +8,13 @@ use crate::error::BotError;
 #[serde_as]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SessionEvent {
-    pub event_type: String, // "identify", "resume", "resumed", "invalid_session"
+    pub event_type: String, // "identify", "resume", "resumed", "invalid_session", "session_limit_wait"
     #[serde(skip_serializing_if = "Option::is_none")]
     pub session_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sequence: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
     #[serde_as(as = "datetime::FromChrono04DateTime")]
     pub timestamp: DateTime<Utc>,
 }
@@ -27,6 +29,7 @@ impl SessionEvent {
             event_type: "identify".to_string(),
             session_id: None,
             sequence: None,
+            details: None,
             timestamp: Utc::now(),
         };
 
@@ -45,6 +48,7 @@ impl SessionEvent {
             event_type: "resume".to_string(),
             session_id: Some(session_id),
             sequence,
+            details: None,
             timestamp: Utc::now(),
         };
 
@@ -59,6 +63,7 @@ impl SessionEvent {
             event_type: "resumed".to_string(),
             session_id: None,
             sequence: None,
+            details: None,
             timestamp: Utc::now(),
         };
 
@@ -73,6 +78,7 @@ impl SessionEvent {
             event_type: "ready".to_string(),
             session_id: Some(session_id),
             sequence: None,
+            details: None,
             timestamp: Utc::now(),
         };
 
@@ -87,6 +93,29 @@ impl SessionEvent {
             event_type: "invalid_session".to_string(),
             session_id: None,
             sequence: None,
+            details: None,
+            timestamp: Utc::now(),
+        };
+
+        collection.insert_one(event).await?;
+        Ok(())
+    }
+
+    pub async fn log_session_limit_wait(
+        db: &mongodb::Database,
+        remaining: i32,
+        wait_seconds: f64,
+    ) -> Result<(), BotError> {
+        let collection: Collection<SessionEvent> = db.collection(Self::COLLECTION_NAME);
+
+        let event = SessionEvent {
+            event_type: "session_limit_wait".to_string(),
+            session_id: None,
+            sequence: None,
+            details: Some(format!(
+                "{} session starts remaining, waiting {:.0}s for the daily limit to reset",
+                remaining, wait_seconds
+            )),
             timestamp: Utc::now(),
         };
 
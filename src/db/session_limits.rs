@@ -32,6 +32,9 @@ impl SessionLimit {
     const COLLECTION_NAME: &'static str = "discord_session_limits";
     const DOC_ID: &'static str = "session_limit";
 
+    /// Session starts kept in reserve so a restart never hits a zero budget
+    const SAFETY_MARGIN: i32 = 10;
+
     /// Update session limit information from Gateway Bot endpoint
     pub async fn update(
         db: &mongodb::Database,
@@ -61,7 +64,6 @@ impl SessionLimit {
     }
 
     /// Get current session limit information
-    #[allow(dead_code)]
     pub async fn get(db: &mongodb::Database) -> Result<Option<SessionLimit>, BotError> {
         let collection: Collection<SessionLimit> = db.collection(Self::COLLECTION_NAME);
         
@@ -72,18 +74,30 @@ impl SessionLimit {
         Ok(limit)
     }
 
-    /// Check if we can start a new session
-    #[allow(dead_code)]
+    /// Count one session start against the stored budget
+    pub async fn consume(db: &mongodb::Database) -> Result<(), BotError> {
+        let collection: Collection<SessionLimit> = db.collection(Self::COLLECTION_NAME);
+
+        collection
+            .update_one(doc! { "_id": Self::DOC_ID }, doc! { "$inc": { "remaining": -1 } })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Check if we can start a new session without dipping into the safety margin
     pub fn can_start_session(&self) -> bool {
-        self.remaining > 0
+        self.remaining > Self::SAFETY_MARGIN
     }
 
     /// Get seconds to wait before we can start a new session
-    #[allow(dead_code)]
     pub fn retry_after_seconds(&self) -> f64 {
-        if self.remaining > 0 {
+        if self.can_start_session() {
             return 0.0;
         }
-        self.reset_after as f64 / 1000.0
+
+        // reset_after counts from the moment the limit was fetched
+        let reset_at = self.updated_at + chrono::Duration::milliseconds(self.reset_after);
+        (reset_at - Utc::now()).num_milliseconds().max(0) as f64 / 1000.0
     }
 }
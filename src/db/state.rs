@@ -7,12 +7,23 @@ use crate::error::BotError;
 pub struct DiscordState {
     #[serde(rename = "_id")]
     pub id: String,
-    pub session_id: Option<String>,
-    pub sequence: Option<u64>,
+    #[serde(default)]
+    pub shards: Vec<ShardSession>,
     pub bot_user_id: Option<String>,
     pub rate_limit: Option<f64>, // requests per second
 }
 
+/// Gateway session of a single shard
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ShardSession {
+    pub shard_id: u32,
+    /// Total shard count the session was identified with
+    pub shard_count: u32,
+    pub session_id: Option<String>,
+    pub resume_gateway_url: Option<String>,
+    pub sequence: Option<u64>,
+}
+
 impl DiscordState {
     const COLLECTION_NAME: &'static str = "discord_state";
     const STATE_ID: &'static str = "bot_state";
@@ -24,8 +35,7 @@ impl DiscordState {
 
         Ok(state.unwrap_or(DiscordState {
             id: Self::STATE_ID.to_string(),
-            session_id: None,
-            sequence: None,
+            shards: Vec::new(),
             bot_user_id: None,
             rate_limit: None,
         }))
@@ -1,37 +1,12 @@
-use reqwest::Client;
 use crate::error::{BotError};
+use crate::commands::SlashCommand;
 use crate::discord::discord::*;
 use crate::state;
+use serde_json::Value;
 
-pub async fn get_gateway_bot_info(client: &Client, token: &str) -> Result<GatewayBotInfo, BotError> {
-    let response = client
-        .get("https://discord.com/api/v10/gateway/bot")
-        .header("Authorization", format!("Bot {}", token))
-        .send()
-        .await?;
+pub async fn get_gateway_bot_info() -> Result<GatewayBotInfo, BotError> {
+    let bot_info = state::http().await.get_gateway_bot().await?;
 
-    // Store rate limits
-    let _ = crate::db::rate_limits::RateLimit::update_from_headers(
-        &*state::db().await,
-        "/gateway/bot".to_string(),
-        response.headers(),
-    ).await;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_default();
-        
-        if let Ok(discord_err) = serde_json::from_str::<DiscordErrorResponse>(&error_text) {
-            return Err(BotError::new("discord_api_error")
-                .push_str(format!("GET /gateway/bot: {}", discord_err)));
-        }
-        
-        return Err(BotError::new("http_error")
-            .push_str(format!("GET /gateway/bot: {} - {}", status, error_text)));
-    }
-
-    let bot_info: GatewayBotInfo = response.json().await?;
-    
     // Store session limits
     let _ = crate::db::session_limits::SessionLimit::update(
         &*state::db().await,
@@ -41,184 +16,333 @@ pub async fn get_gateway_bot_info(client: &Client, token: &str) -> Result<Gatewa
         bot_info.session_start_limit.max_concurrency,
         bot_info.shards,
     ).await;
-    
+
     Ok(bot_info)
 }
 
-pub async fn get_gateway_url(client: &Client, token: &str) -> Result<String, BotError> {
-    let response = client
-        .get("https://discord.com/api/v10/gateway")
-        .header("Authorization", format!("Bot {}", token))
-        .send()
-        .await?;
-
-    // Store rate limits
-    let _ = crate::db::rate_limits::RateLimit::update_from_headers(
-        &*state::db().await,
-        "/gateway".to_string(),
-        response.headers(),
-    ).await;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_default();
-        
-        // Try to parse as Discord error
-        if let Ok(discord_err) = serde_json::from_str::<DiscordErrorResponse>(&error_text) {
-            return Err(BotError::new("discord_api_error")
-                .push_str(format!("GET /gateway: {}", discord_err)));
-        }
-        
-        return Err(BotError::new("http_error")
-            .push_str(format!("GET /gateway: {} - {}", status, error_text)));
+/// Append API version, encoding and optional transport compression to a bare gateway URL
+pub fn with_gateway_params(url: &str, compress: bool) -> String {
+    let mut params = String::from("v=10&encoding=json");
+    if compress {
+        params.push_str("&compress=zlib-stream");
     }
 
-    let gateway: GatewayResponse = response.json().await?;
-    let url = if gateway.url.ends_with('/') {
-        format!("{}?v=10&encoding=json", gateway.url)
+    if url.ends_with('/') {
+        format!("{}?{}", url, params)
     } else {
-        format!("{}/?v=10&encoding=json", gateway.url)
-    };
-    Ok(url)
-}
-
-pub async fn get_application_id(client: &Client, token: &str) -> Result<String, BotError> {
-    let response = client
-        .get("https://discord.com/api/v10/oauth2/applications/@me")
-        .header("Authorization", format!("Bot {}", token))
-        .send()
-        .await?;
-
-    // Store rate limits
-    let _ = crate::db::rate_limits::RateLimit::update_from_headers(
-        &*state::db().await,
-        "/oauth2/applications/@me".to_string(),
-        response.headers(),
-    ).await;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_default();
-        
-        if let Ok(discord_err) = serde_json::from_str::<DiscordErrorResponse>(&error_text) {
-            return Err(BotError::new("discord_api_error")
-                .push_str(format!("GET /oauth2/applications/@me: {}", discord_err)));
-        }
-        
-        return Err(BotError::new("http_error")
-            .push_str(format!("GET /oauth2/applications/@me: {} - {}", status, error_text)));
+        format!("{}/?{}", url, params)
     }
+}
 
-    let app_info: ApplicationInfo = response.json().await?;
+pub async fn get_application_id() -> Result<String, BotError> {
+    let app_info = state::http().await.get_current_application().await?;
     Ok(app_info.id)
 }
 
-pub async fn register_slash_commands(client: &Client, token: &str, app_id: &str) -> Result<(), BotError> {
+/// Register slash commands, in the dev guild when one is given, otherwise globally.
+///
+/// The PUT is skipped when Discord already has the same commands, unless `force` is set.
+pub async fn register_slash_commands(
+    app_id: &str,
+    dev_guild_id: Option<&str>,
+    force: bool,
+) -> Result<(), BotError> {
     let commands = crate::commands::all_commands();
-    
+    let http = state::http().await;
+
     println!("[INFO] Preparing to register {} slash commands:", commands.len());
     for cmd in &commands {
         println!("[INFO]   - /{}: {}", cmd.name, cmd.description);
     }
 
+    // Guild commands update instantly, global ones can take up to an hour to propagate
+    let existing = match dev_guild_id {
+        Some(guild_id) => http.get_guild_commands(app_id, guild_id).await?,
+        None => http.get_global_commands(app_id).await?,
+    };
+
+    if !force && commands_match(&commands, &existing)? {
+        println!("[INFO] Registered commands are up to date, skipping registration");
+        return Ok(());
+    }
+
     println!("[INFO] Sending registration request to Discord API...");
-    let response = client
-        .put(&format!(
-            "https://discord.com/api/v10/applications/{}/commands",
-            app_id
-        ))
-        .header("Authorization", format!("Bot {}", token))
-        .header("Content-Type", "application/json")
-        .json(&commands)
-        .send()
-        .await?;
-
-    println!("[INFO] Discord API response status: {}", response.status());
-
-    // Store rate limits
-    let _ = crate::db::rate_limits::RateLimit::update_from_headers(
-        &*state::db().await,
-        format!("/applications/{}/commands", app_id),
-        response.headers(),
-    ).await;
+    let result = match dev_guild_id {
+        Some(guild_id) => {
+            http.bulk_overwrite_guild_commands(app_id, guild_id, &commands)
+                .await
+        }
+        None => http.bulk_overwrite_global_commands(app_id, &commands).await,
+    };
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_default();
-        
-        println!("[ERROR] Discord API returned error: {} - {}", status, error_text);
-        
-        if let Ok(discord_err) = serde_json::from_str::<DiscordErrorResponse>(&error_text) {
-            return Err(BotError::new("discord_api_error")
-                .push_str(format!("PUT /applications/{}/commands: {}", app_id, discord_err)));
+    let registered_commands = match result {
+        Ok(registered) => registered,
+        Err(e) => {
+            println!("[ERROR] Discord API returned error: {}", e);
+            return Err(e);
         }
-        
-        return Err(BotError::new("http_error")
-            .push_str(format!("PUT /applications/{}/commands: {} - {}", app_id, status, error_text)));
-    }
+    };
 
-    // Parse response to see what commands were actually registered
-    let response_text = response.text().await.unwrap_or_default();
-    if let Ok(registered_commands) = serde_json::from_str::<Vec<serde_json::Value>>(&response_text) {
-        println!("[INFO] Discord confirmed {} commands registered:", registered_commands.len());
-        for cmd in registered_commands {
-            if let Some(name) = cmd.get("name").and_then(|n| n.as_str()) {
-                if let Some(id) = cmd.get("id").and_then(|i| i.as_str()) {
-                    println!("[INFO]   - /{} (ID: {})", name, id);
-                } else {
-                    println!("[INFO]   - /{}", name);
-                }
+    // Show what commands were actually registered
+    println!("[INFO] Discord confirmed {} commands registered:", registered_commands.len());
+    for cmd in registered_commands {
+        if let Some(name) = cmd.get("name").and_then(|n| n.as_str()) {
+            if let Some(id) = cmd.get("id").and_then(|i| i.as_str()) {
+                println!("[INFO]   - /{} (ID: {})", name, id);
+            } else {
+                println!("[INFO]   - /{}", name);
             }
         }
-    } else {
-        println!("[INFO] Commands registration successful (unable to parse response details)");
     }
 
     Ok(())
 }
 
+/// Fields Discord sets on registered commands that we never send
+const DISCORD_ONLY_KEYS: [&str; 9] = [
+    "id",
+    "application_id",
+    "version",
+    "guild_id",
+    "default_permission",
+    "dm_permission",
+    "nsfw",
+    "contexts",
+    "integration_types",
+];
+
+/// Whether Discord already has exactly these commands.
+///
+/// Both sides are normalized first: Discord-only fields are dropped, and since Discord fills in
+/// defaults we don't send, absent, null, false and empty values count as the same.
+fn commands_match(commands: &[SlashCommand], existing: &[Value]) -> Result<bool, BotError> {
+    let mut desired = commands
+        .iter()
+        .map(|command| serde_json::to_value(command).map(normalize_command))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut registered: Vec<Value> = existing.iter().cloned().map(normalize_command).collect();
+
+    // Discord doesn't keep the order of the bulk overwrite
+    desired.sort_by_key(command_key);
+    registered.sort_by_key(command_key);
+
+    Ok(desired == registered)
+}
+
+fn normalize_command(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .filter(|(key, _)| !DISCORD_ONLY_KEYS.contains(&key.as_str()))
+                .map(|(key, value)| (key, normalize_command(value)))
+                .filter(|(_, value)| !is_unset(value))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(normalize_command).collect()),
+        other => other,
+    }
+}
+
+fn is_unset(value: &Value) -> bool {
+    match value {
+        Value::Null | Value::Bool(false) => true,
+        Value::Array(items) => items.is_empty(),
+        Value::Object(fields) => fields.is_empty(),
+        _ => false,
+    }
+}
+
+/// Commands are unique by type and name
+fn command_key(command: &Value) -> (u64, String) {
+    (
+        command.get("type").and_then(Value::as_u64).unwrap_or(1),
+        command.get("name").and_then(Value::as_str).unwrap_or_default().to_string(),
+    )
+}
+
+/// Acknowledge an interaction now and fill in the response later (type 5)
+pub async fn defer_interaction(
+    interaction_id: &str,
+    interaction_token: &str,
+) -> Result<(), BotError> {
+    let response_data = InteractionResponse {
+        response_type: 5,
+        data: None,
+    };
+
+    state::http()
+        .await
+        .create_interaction_response(interaction_id, interaction_token, &response_data)
+        .await
+}
+
 pub async fn respond_to_interaction(
-    client: &Client,
-    token: &str,
     interaction_id: &str,
     interaction_token: &str,
     content: String,
 ) -> Result<(), BotError> {
     let response_data = InteractionResponse {
         response_type: 4,
-        data: Some(InteractionResponseData { content }),
+        data: Some(InteractionResponseData {
+            content,
+            flags: None,
+        }),
     };
 
-    let response = client
-        .post(&format!(
-            "https://discord.com/api/v10/interactions/{}/{}/callback",
-            interaction_id, interaction_token
-        ))
-        .header("Authorization", format!("Bot {}", token))
-        .header("Content-Type", "application/json")
-        .json(&response_data)
-        .send()
-        .await?;
-
-    // Store rate limits
-    let _ = crate::db::rate_limits::RateLimit::update_from_headers(
-        &*state::db().await,
-        "/interactions/callback".to_string(),
-        response.headers(),
-    ).await;
+    state::http()
+        .await
+        .create_interaction_response(interaction_id, interaction_token, &response_data)
+        .await
+}
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_default();
-        
-        if let Ok(discord_err) = serde_json::from_str::<DiscordErrorResponse>(&error_text) {
-            return Err(BotError::new("discord_api_error")
-                .push_str(format!("POST /interactions/{}/{}/callback: {}", interaction_id, interaction_token, discord_err)));
-        }
-        
-        return Err(BotError::new("http_error")
-            .push_str(format!("POST /interactions/{}/{}/callback: {} - {}", interaction_id, interaction_token, status, error_text)));
+/// Answer an autocomplete interaction with suggested choices (type 8)
+pub async fn respond_autocomplete(
+    interaction_id: &str,
+    interaction_token: &str,
+    choices: Vec<CommandOptionChoice>,
+) -> Result<(), BotError> {
+    let response_data = AutocompleteResponse {
+        response_type: 8,
+        data: AutocompleteData { choices },
+    };
+
+    state::http()
+        .await
+        .create_interaction_response(interaction_id, interaction_token, &response_data)
+        .await
+}
+
+/// Open a modal form, answered later by a MODAL_SUBMIT interaction
+pub async fn show_modal(
+    interaction_id: &str,
+    interaction_token: &str,
+    data: ModalData,
+) -> Result<(), BotError> {
+    let response_data = ModalResponse {
+        response_type: 9,
+        data,
+    };
+
+    state::http()
+        .await
+        .create_interaction_response(interaction_id, interaction_token, &response_data)
+        .await
+}
+
+/// Respond with a message only the invoking user can see
+pub async fn respond_ephemeral(
+    interaction_id: &str,
+    interaction_token: &str,
+    content: String,
+) -> Result<(), BotError> {
+    let response_data = InteractionResponse {
+        response_type: 4,
+        data: Some(InteractionResponseData {
+            content,
+            flags: Some(64),
+        }),
+    };
+
+    state::http()
+        .await
+        .create_interaction_response(interaction_id, interaction_token, &response_data)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandOption;
+    use serde_json::json;
+
+    fn commands() -> Vec<SlashCommand> {
+        vec![
+            SlashCommand {
+                name: "rembg".to_string(),
+                command_type: 1,
+                description: "Remove the background".to_string(),
+                options: vec![
+                    CommandOption::new(OptionType::Integer, "threshold", "Alpha cutoff").range(0, 255),
+                ],
+                default_member_permissions: None,
+            },
+            SlashCommand {
+                name: "config".to_string(),
+                command_type: 1,
+                description: "Server settings".to_string(),
+                options: Vec::new(),
+                default_member_permissions: Some("32".to_string()),
+            },
+        ]
     }
 
-    Ok(())
+    fn registered() -> Vec<Value> {
+        vec![
+            json!({
+                "id": "1",
+                "application_id": "2",
+                "version": "3",
+                "type": 1,
+                "name": "config",
+                "description": "Server settings",
+                "default_member_permissions": "32",
+                "dm_permission": true,
+                "nsfw": false,
+                "contexts": null,
+                "integration_types": [0],
+            }),
+            json!({
+                "id": "4",
+                "application_id": "2",
+                "version": "5",
+                "type": 1,
+                "name": "rembg",
+                "description": "Remove the background",
+                "default_member_permissions": null,
+                "options": [{
+                    "type": 4,
+                    "name": "threshold",
+                    "description": "Alpha cutoff",
+                    "required": false,
+                    "autocomplete": false,
+                    "choices": [],
+                    "min_value": 0,
+                    "max_value": 255,
+                }],
+            }),
+        ]
+    }
+
+    #[test]
+    fn registered_commands_match_despite_discord_fields_and_order() {
+        assert!(commands_match(&commands(), &registered()).unwrap());
+    }
+
+    #[test]
+    fn changed_option_is_a_mismatch() {
+        let mut registered = registered();
+        registered[1]["options"][0]["max_value"] = json!(100);
+
+        assert!(!commands_match(&commands(), &registered).unwrap());
+    }
+
+    #[test]
+    fn missing_or_extra_command_is_a_mismatch() {
+        let registered = registered();
+
+        assert!(!commands_match(&commands(), &registered[..1]).unwrap());
+        assert!(!commands_match(&commands()[..1], &registered).unwrap());
+    }
+
+    #[test]
+    fn normalize_drops_unset_values_recursively() {
+        let normalized = normalize_command(json!({
+            "name": "icon",
+            "nsfw": true,
+            "options": [{ "name": "frame", "required": false, "choices": [], "extra": {} }],
+        }));
+
+        assert_eq!(normalized, json!({ "name": "icon", "options": [{ "name": "frame" }] }));
+    }
 }
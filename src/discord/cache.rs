@@ -0,0 +1,146 @@
+//! Guilds, channels, roles and the bot's own member, kept current by gateway dispatch events
+
+use crate::discord::discord::{Channel, EventType, Role};
+use crate::state;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default)]
+pub struct CachedGuild {
+    pub owner_id: String,
+    pub roles: HashMap<String, Role>,
+    /// Role ids of the bot's member, `None` until Discord sends the member
+    pub bot_roles: Option<Vec<String>>,
+}
+
+static GUILDS: Lazy<DashMap<String, CachedGuild>> = Lazy::new(DashMap::new);
+
+/// Guild channels and threads by id
+static CHANNELS: Lazy<DashMap<String, Channel>> = Lazy::new(DashMap::new);
+
+pub fn guild_owner_id(guild_id: &str) -> Option<String> {
+    GUILDS.get(guild_id).map(|guild| guild.owner_id.clone())
+}
+
+pub fn channel(channel_id: &str) -> Option<Channel> {
+    CHANNELS.get(channel_id).map(|channel| channel.clone())
+}
+
+pub fn guild_roles(guild_id: &str) -> Option<Vec<Role>> {
+    GUILDS
+        .get(guild_id)
+        .map(|guild| guild.roles.values().cloned().collect())
+}
+
+pub fn bot_roles(guild_id: &str) -> Option<Vec<String>> {
+    GUILDS.get(guild_id).and_then(|guild| guild.bot_roles.clone())
+}
+
+/// Apply a dispatch event to the cache
+pub async fn handle_event(event_type: EventType, d: &Value) {
+    match event_type {
+        EventType::GuildCreate => insert_guild(d, &state::bot_user_id().await),
+        EventType::GuildUpdate => {
+            if let Some(mut guild) = d["id"].as_str().and_then(|id| GUILDS.get_mut(id)) {
+                guild.owner_id = d["owner_id"].as_str().unwrap_or_default().to_string();
+                guild.roles = parse_roles(&d["roles"]);
+            }
+        }
+        EventType::GuildDelete => {
+            if let Some(guild_id) = d["id"].as_str() {
+                GUILDS.remove(guild_id);
+                CHANNELS.retain(|_, channel| channel.guild_id.as_deref() != Some(guild_id));
+            }
+        }
+        EventType::ChannelCreate
+        | EventType::ChannelUpdate
+        | EventType::ThreadCreate
+        | EventType::ThreadUpdate => {
+            // DM channels carry no guild and are never cached
+            if let Some(channel) = parse::<Channel>(d).filter(|channel| channel.guild_id.is_some()) {
+                CHANNELS.insert(channel.id.clone(), channel);
+            }
+        }
+        EventType::ChannelDelete | EventType::ThreadDelete => {
+            if let Some(channel_id) = d["id"].as_str() {
+                CHANNELS.remove(channel_id);
+            }
+        }
+        EventType::GuildRoleCreate | EventType::GuildRoleUpdate => {
+            if let Some(mut guild) = d["guild_id"].as_str().and_then(|id| GUILDS.get_mut(id))
+                && let Some(role) = parse::<Role>(&d["role"])
+            {
+                guild.roles.insert(role.id.clone(), role);
+            }
+        }
+        EventType::GuildRoleDelete => {
+            if let Some(mut guild) = d["guild_id"].as_str().and_then(|id| GUILDS.get_mut(id))
+                && let Some(role_id) = d["role_id"].as_str()
+            {
+                guild.roles.remove(role_id);
+            }
+        }
+        EventType::GuildMemberUpdate => {
+            let bot_user_id = state::bot_user_id().await;
+            if d["user"]["id"].as_str() == Some(bot_user_id.as_str())
+                && let Some(mut guild) = d["guild_id"].as_str().and_then(|id| GUILDS.get_mut(id))
+            {
+                guild.bot_roles = parse(&d["roles"]);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// GUILD_CREATE brings the whole guild: roles, channels, active threads and the bot's member
+fn insert_guild(d: &Value, bot_user_id: &str) {
+    let Some(guild_id) = d["id"].as_str() else {
+        return;
+    };
+
+    // Channels inside GUILD_CREATE come without their guild_id
+    let channels = d["channels"].as_array().into_iter().flatten();
+    let threads = d["threads"].as_array().into_iter().flatten();
+    for channel in channels.chain(threads).filter_map(parse::<Channel>) {
+        CHANNELS.insert(
+            channel.id.clone(),
+            Channel {
+                guild_id: Some(guild_id.to_string()),
+                ..channel
+            },
+        );
+    }
+
+    let bot_roles = d["members"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|member| member["user"]["id"].as_str() == Some(bot_user_id))
+        .and_then(|member| parse(&member["roles"]));
+
+    GUILDS.insert(
+        guild_id.to_string(),
+        CachedGuild {
+            owner_id: d["owner_id"].as_str().unwrap_or_default().to_string(),
+            roles: parse_roles(&d["roles"]),
+            bot_roles,
+        },
+    );
+}
+
+fn parse_roles(roles: &Value) -> HashMap<String, Role> {
+    roles
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(parse::<Role>)
+        .map(|role| (role.id.clone(), role))
+        .collect()
+}
+
+fn parse<T: DeserializeOwned>(value: &Value) -> Option<T> {
+    serde_json::from_value(value.clone()).ok()
+}
@@ -0,0 +1,132 @@
+use crate::error::BotError;
+use flate2::{Decompress, FlushDecompress};
+
+/// Every complete zlib-stream message ends with a Z_SYNC_FLUSH marker
+const ZLIB_SUFFIX: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Inflate context of a `compress=zlib-stream` gateway connection.
+///
+/// Discord compresses the whole connection as one zlib stream, so the context
+/// must live as long as the socket and see every binary frame in order.
+pub struct ZlibStream {
+    inflater: Decompress,
+    buffer: Vec<u8>,
+}
+
+impl Default for ZlibStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ZlibStream {
+    pub fn new() -> Self {
+        Self {
+            inflater: Decompress::new(true),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Feed a binary frame; returns the decoded payload once a full message has arrived
+    pub fn push(&mut self, frame: &[u8]) -> Result<Option<String>, BotError> {
+        self.buffer.extend_from_slice(frame);
+
+        if !self.buffer.ends_with(&ZLIB_SUFFIX) {
+            return Ok(None);
+        }
+
+        let mut output = Vec::with_capacity(self.buffer.len() * 4);
+        let mut consumed = 0;
+
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(self.buffer.len().max(1024));
+            }
+
+            let total_in = self.inflater.total_in();
+            let total_out = self.inflater.total_out();
+
+            self.inflater.decompress_vec(
+                &self.buffer[consumed..],
+                &mut output,
+                FlushDecompress::Sync,
+            )?;
+
+            consumed += (self.inflater.total_in() - total_in) as usize;
+            let progressed = self.inflater.total_in() != total_in
+                || self.inflater.total_out() != total_out;
+
+            // Done once all input is consumed and the output buffer wasn't the limit
+            if (consumed >= self.buffer.len() && output.len() < output.capacity()) || !progressed {
+                break;
+            }
+        }
+
+        self.buffer.clear();
+
+        let text = String::from_utf8(output)
+            .map_err(|e| BotError::new("gateway_payload_utf8").push_std(e))?;
+
+        Ok(Some(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compress, Compression, FlushCompress};
+
+    /// One gateway message as Discord sends it: the shared stream, sync-flushed
+    fn compress(compressor: &mut Compress, text: &str) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(text.len() + 1024);
+        compressor
+            .compress_vec(text.as_bytes(), &mut frame, FlushCompress::Sync)
+            .unwrap();
+        assert!(frame.ends_with(&ZLIB_SUFFIX));
+        frame
+    }
+
+    #[test]
+    fn decodes_a_complete_message() {
+        let mut compressor = Compress::new(Compression::default(), true);
+        let mut stream = ZlibStream::new();
+
+        let frame = compress(&mut compressor, r#"{"op":10}"#);
+        assert_eq!(stream.push(&frame).unwrap().as_deref(), Some(r#"{"op":10}"#));
+    }
+
+    #[test]
+    fn waits_for_the_suffix_before_decoding() {
+        let mut compressor = Compress::new(Compression::default(), true);
+        let mut stream = ZlibStream::new();
+
+        let frame = compress(&mut compressor, r#"{"op":11}"#);
+        let (head, tail) = frame.split_at(frame.len() - 2);
+
+        assert_eq!(stream.push(head).unwrap(), None);
+        assert_eq!(stream.push(tail).unwrap().as_deref(), Some(r#"{"op":11}"#));
+    }
+
+    #[test]
+    fn keeps_the_context_between_messages() {
+        let mut compressor = Compress::new(Compression::default(), true);
+        let mut stream = ZlibStream::new();
+        let payload = r#"{"op":0,"t":"MESSAGE_CREATE","d":{"content":"hello"}}"#;
+
+        // The second frame refers back to the first one's window
+        for _ in 0..2 {
+            let frame = compress(&mut compressor, payload);
+            assert_eq!(stream.push(&frame).unwrap().as_deref(), Some(payload));
+        }
+    }
+
+    #[test]
+    fn grows_the_output_for_highly_compressed_payloads() {
+        let mut compressor = Compress::new(Compression::best(), true);
+        let mut stream = ZlibStream::new();
+        let payload = format!(r#"{{"d":"{}"}}"#, "a".repeat(200_000));
+
+        let frame = compress(&mut compressor, &payload);
+        assert_eq!(stream.push(&frame).unwrap(), Some(payload));
+    }
+}
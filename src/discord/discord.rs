@@ -1,6 +1,8 @@
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, Serializer};
 use serde_json::Value;
 use crate::discord::message::attachment::Attachment;
+use crate::discord::message::component::ActionRow;
 
 // Discord Gateway opcodes
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -9,6 +11,7 @@ pub enum Opcode {
     Heartbeat = 1,
     Identify = 2,
     Resume = 6,
+    Reconnect = 7,
     InvalidSession = 9,
     Hello = 10,
     HeartbeatAck = 11,
@@ -22,6 +25,7 @@ impl Opcode {
             1 => Self::Heartbeat,
             2 => Self::Identify,
             6 => Self::Resume,
+            7 => Self::Reconnect,
             9 => Self::InvalidSession,
             10 => Self::Hello,
             11 => Self::HeartbeatAck,
@@ -37,6 +41,19 @@ pub enum EventType {
     Resumed,
     InteractionCreate,
     MessageCreate,
+    GuildCreate,
+    GuildUpdate,
+    GuildDelete,
+    ChannelCreate,
+    ChannelUpdate,
+    ChannelDelete,
+    ThreadCreate,
+    ThreadUpdate,
+    ThreadDelete,
+    GuildRoleCreate,
+    GuildRoleUpdate,
+    GuildRoleDelete,
+    GuildMemberUpdate,
     Unknown,
 }
 
@@ -47,17 +64,24 @@ impl EventType {
             "RESUMED" => Self::Resumed,
             "INTERACTION_CREATE" => Self::InteractionCreate,
             "MESSAGE_CREATE" => Self::MessageCreate,
+            "GUILD_CREATE" => Self::GuildCreate,
+            "GUILD_UPDATE" => Self::GuildUpdate,
+            "GUILD_DELETE" => Self::GuildDelete,
+            "CHANNEL_CREATE" => Self::ChannelCreate,
+            "CHANNEL_UPDATE" => Self::ChannelUpdate,
+            "CHANNEL_DELETE" => Self::ChannelDelete,
+            "THREAD_CREATE" => Self::ThreadCreate,
+            "THREAD_UPDATE" => Self::ThreadUpdate,
+            "THREAD_DELETE" => Self::ThreadDelete,
+            "GUILD_ROLE_CREATE" => Self::GuildRoleCreate,
+            "GUILD_ROLE_UPDATE" => Self::GuildRoleUpdate,
+            "GUILD_ROLE_DELETE" => Self::GuildRoleDelete,
+            "GUILD_MEMBER_UPDATE" => Self::GuildMemberUpdate,
             _ => Self::Unknown,
         }
     }
 }
 
-// Gateway response
-#[derive(Debug, Deserialize)]
-pub struct GatewayResponse {
-    pub url: String,
-}
-
 // Discord event from WebSocket
 #[derive(Debug, Deserialize)]
 pub struct DiscordEvent {
@@ -90,8 +114,30 @@ pub struct Interaction {
     pub data: Option<InteractionData>,
     pub token: String,
     pub channel_id: Option<String>,
+    pub guild_id: Option<String>,
+    pub application_id: Option<String>,
     pub message: Option<InteractionMessage>,
+    /// Set for interactions in DMs
+    pub user: Option<InteractionUser>,
+    /// Set for interactions in guilds
+    pub member: Option<InteractionMember>,
+}
+
+impl Interaction {
+    /// User who triggered the interaction, in a guild or in DMs
+    pub fn invoker(&self) -> Option<&InteractionUser> {
+        self.member
+            .as_ref()
+            .and_then(|member| member.user.as_ref())
+            .or(self.user.as_ref())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InteractionMember {
     pub user: Option<InteractionUser>,
+    #[serde(default)]
+    pub roles: Vec<String>,
 }
 
 #[allow(dead_code)]
@@ -111,14 +157,123 @@ pub struct InteractionMessage {
 #[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct InteractionData {
+    /// Command name; empty for component interactions
+    #[serde(default)]
     pub name: String,
+    /// Developer-defined id of the clicked button or used select
+    pub custom_id: Option<String>,
+    pub component_type: Option<u8>,
+    /// Values picked in a select menu
+    #[serde(default)]
+    pub values: Vec<String>,
+    /// Submitted text inputs of a modal
+    #[serde(default)]
+    pub components: Vec<ActionRow>,
+    /// Message or user a context menu command was used on
+    pub target_id: Option<String>,
+    #[serde(default)]
+    pub options: Vec<InteractionDataOption>,
     pub resolved: Option<InteractionResolved>,
 }
 
+/// https://discord.com/developers/docs/interactions/receiving-and-responding#interaction-object-application-command-interaction-data-option-structure
+#[derive(Debug, Deserialize)]
+pub struct InteractionDataOption {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub option_type: u8,
+    pub value: Option<Value>,
+    /// Present on subcommands and subcommand groups
+    #[serde(default)]
+    pub options: Vec<InteractionDataOption>,
+    /// Set on the option the user is typing in during autocomplete
+    #[serde(default)]
+    pub focused: bool,
+}
+
+impl InteractionData {
+    /// Names of the invoked subcommand group and subcommand, outermost first
+    pub fn subcommand_path(&self) -> Vec<&str> {
+        let mut path = Vec::new();
+        let mut options = &self.options;
+
+        while let Some(sub) = options.iter().find(|o| Self::is_subcommand(o)) {
+            path.push(sub.name.as_str());
+            options = &sub.options;
+        }
+
+        path
+    }
+
+    /// Value options of the innermost invoked subcommand
+    pub fn leaf_options(&self) -> &[InteractionDataOption] {
+        let mut options = &self.options;
+
+        while let Some(sub) = options.iter().find(|o| Self::is_subcommand(o)) {
+            options = &sub.options;
+        }
+
+        options
+    }
+
+    fn is_subcommand(option: &InteractionDataOption) -> bool {
+        option.option_type == OptionType::SubCommand as u8
+            || option.option_type == OptionType::SubCommandGroup as u8
+    }
+
+    /// Option being typed in, with its partial value
+    pub fn focused_option(&self) -> Option<&InteractionDataOption> {
+        self.leaf_options().iter().find(|o| o.focused)
+    }
+
+    pub fn get_option(&self, name: &str) -> Option<&Value> {
+        self.leaf_options()
+            .iter()
+            .find(|o| o.name == name)
+            .and_then(|o| o.value.as_ref())
+    }
+
+    pub fn get_string(&self, name: &str) -> Option<&str> {
+        self.get_option(name)?.as_str()
+    }
+
+    pub fn get_integer(&self, name: &str) -> Option<i64> {
+        self.get_option(name)?.as_i64()
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        self.get_option(name)?.as_bool()
+    }
+
+    /// Trimmed, non-empty value of a modal text input
+    pub fn text_value(&self, custom_id: &str) -> Option<&str> {
+        self.components
+            .iter()
+            .flat_map(|row| &row.components)
+            .find(|c| c.custom_id.as_deref() == Some(custom_id))
+            .and_then(|c| c.value.as_deref())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Message a message context menu command was used on
+    pub fn target_message(&self) -> Option<&ResolvedMessage> {
+        let id = self.target_id.as_ref()?;
+        self.resolved.as_ref()?.messages.as_ref()?.get(id)
+    }
+
+    /// Attachment options carry an id that points into `resolved.attachments`
+    pub fn get_attachment(&self, name: &str) -> Option<&Attachment> {
+        let id = self.get_string(name)?;
+        self.resolved.as_ref()?.attachments.as_ref()?.get(id)
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct InteractionResolved {
     pub messages: Option<std::collections::HashMap<String, ResolvedMessage>>,
+    pub attachments: Option<std::collections::HashMap<String, Attachment>>,
 }
 
 #[allow(dead_code)]
@@ -128,17 +283,112 @@ pub struct ResolvedMessage {
     pub attachments: Option<Vec<Attachment>>,
 }
 
+/// https://discord.com/developers/docs/interactions/application-commands#application-command-object-application-command-option-type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionType {
+    SubCommand = 1,
+    SubCommandGroup = 2,
+    String = 3,
+    Integer = 4,
+    Boolean = 5,
+    Channel = 7,
+    Role = 8,
+    Attachment = 11,
+}
+
+impl Serialize for OptionType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandOptionChoice {
+    pub name: String,
+    pub value: Value,
+}
+
+/// Interaction tokens stop working this long after the interaction was created
+const INTERACTION_TOKEN_LIFETIME: chrono::TimeDelta = chrono::TimeDelta::minutes(15);
+
+/// Milliseconds between the Unix epoch and the Discord epoch snowflakes count from
+const DISCORD_EPOCH_MS: i64 = 1_420_070_400_000;
+
+/// Interaction a job answers through the webhook endpoints instead of a channel message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InteractionTarget {
+    pub application_id: String,
+    pub token: String,
+    /// When the interaction was created; jobs stored without it count as expired
+    #[serde(default)]
+    pub created: DateTime<Utc>,
+}
+
+impl InteractionTarget {
+    pub fn from_interaction(interaction: &Interaction) -> Option<Self> {
+        Some(Self {
+            application_id: interaction.application_id.clone()?,
+            token: interaction.token.clone(),
+            created: snowflake_time(&interaction.id).unwrap_or_else(Utc::now),
+        })
+    }
+
+    /// Whether the token is too old for the webhook endpoints, replies go to the channel instead
+    pub fn expired(&self) -> bool {
+        Utc::now() - self.created >= INTERACTION_TOKEN_LIFETIME
+    }
+}
+
+/// Creation time encoded in the upper bits of a snowflake id
+fn snowflake_time(id: &str) -> Option<DateTime<Utc>> {
+    let id = id.parse::<u64>().ok()?;
+    DateTime::from_timestamp_millis((id >> 22) as i64 + DISCORD_EPOCH_MS)
+}
+
 // Interaction response to Discord
 #[derive(Debug, Serialize)]
 pub struct InteractionResponse {
     #[serde(rename = "type")]
     pub response_type: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<InteractionResponseData>,
 }
 
+/// Interaction response with autocomplete suggestions (type 8)
+#[derive(Debug, Serialize)]
+pub struct AutocompleteResponse {
+    #[serde(rename = "type")]
+    pub response_type: u8,
+    pub data: AutocompleteData,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AutocompleteData {
+    pub choices: Vec<CommandOptionChoice>,
+}
+
+/// Interaction response opening a modal (type 9)
+#[derive(Debug, Serialize)]
+pub struct ModalResponse {
+    #[serde(rename = "type")]
+    pub response_type: u8,
+    pub data: ModalData,
+}
+
+/// https://discord.com/developers/docs/interactions/receiving-and-responding#interaction-response-object-modal
+#[derive(Debug, Serialize)]
+pub struct ModalData {
+    pub custom_id: String,
+    pub title: String,
+    pub components: Vec<ActionRow>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct InteractionResponseData {
     pub content: String,
+    /// 64 = EPHEMERAL, only the invoking user sees the response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<u64>,
 }
 
 // Discord API error response
@@ -163,10 +413,8 @@ impl std::fmt::Display for DiscordErrorResponse {
             write!(f, " (retry after {:.3}s)", retry)?;
         }
 
-        if let Some(global) = self.global {
-            if global {
-                write!(f, " [GLOBAL]")?;
-            }
+        if self.global == Some(true) {
+            write!(f, " [GLOBAL]")?;
         }
 
         if let Some(errors) = &self.errors {
@@ -181,6 +429,53 @@ impl std::fmt::Display for DiscordErrorResponse {
     }
 }
 
+/// https://discord.com/developers/docs/resources/channel#channel-object
+#[derive(Debug, Clone, Deserialize)]
+pub struct Channel {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: u8,
+    pub guild_id: Option<String>,
+    /// Category of a channel, or the channel a thread was started in
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub permission_overwrites: Vec<PermissionOverwrite>,
+}
+
+impl Channel {
+    /// ANNOUNCEMENT_THREAD, PUBLIC_THREAD and PRIVATE_THREAD
+    pub fn is_thread(&self) -> bool {
+        matches!(self.kind, 10..=12)
+    }
+}
+
+/// https://discord.com/developers/docs/resources/channel#overwrite-object
+#[derive(Debug, Clone, Deserialize)]
+pub struct PermissionOverwrite {
+    /// Role or user id
+    pub id: String,
+    /// 0 = role, 1 = member
+    #[serde(rename = "type")]
+    pub kind: u8,
+    pub allow: String,
+    pub deny: String,
+}
+
+/// https://discord.com/developers/docs/topics/permissions#role-object
+#[derive(Debug, Clone, Deserialize)]
+pub struct Role {
+    pub id: String,
+    /// Permission bit set, serialized as a string
+    pub permissions: String,
+}
+
+/// https://discord.com/developers/docs/resources/guild#guild-member-object
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GuildMember {
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
 // Application info
 #[derive(Debug, Deserialize)]
 pub struct ApplicationInfo {
@@ -190,7 +485,6 @@ pub struct ApplicationInfo {
 // Gateway Bot info with session limits
 #[derive(Debug, Deserialize)]
 pub struct GatewayBotInfo {
-    #[allow(dead_code)]
     pub url: String,
     pub shards: i32,
     pub session_start_limit: SessionStartLimit,
@@ -203,3 +497,75 @@ pub struct SessionStartLimit {
     pub reset_after: i64, // milliseconds
     pub max_concurrency: i32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn data(options: Value) -> InteractionData {
+        serde_json::from_value(json!({ "name": "icon", "options": options })).unwrap()
+    }
+
+    #[test]
+    fn top_level_options_are_the_leaf_options() {
+        let data = data(json!([
+            { "name": "size", "type": 4, "value": 64 },
+            { "name": "zip", "type": 5, "value": true },
+        ]));
+
+        assert!(data.subcommand_path().is_empty());
+        assert_eq!(data.leaf_options().len(), 2);
+        assert_eq!(data.get_integer("size"), Some(64));
+        assert_eq!(data.get_bool("zip"), Some(true));
+    }
+
+    #[test]
+    fn leaf_options_descend_through_subcommands() {
+        let data = data(json!([{
+            "name": "convert",
+            "type": 2,
+            "options": [{
+                "name": "blp",
+                "type": 1,
+                "options": [{ "name": "quality", "type": 4, "value": 80 }],
+            }],
+        }]));
+
+        assert_eq!(data.subcommand_path(), ["convert", "blp"]);
+        assert_eq!(data.leaf_options().len(), 1);
+        assert_eq!(data.get_integer("quality"), Some(80));
+        assert_eq!(data.get_option("blp"), None);
+    }
+
+    #[test]
+    fn subcommand_without_options_has_no_leaf_options() {
+        let data = data(json!([{ "name": "show", "type": 1 }]));
+
+        assert_eq!(data.subcommand_path(), ["show"]);
+        assert!(data.leaf_options().is_empty());
+    }
+
+    #[test]
+    fn focused_option_is_found_inside_the_subcommand() {
+        let data = data(json!([{
+            "name": "render",
+            "type": 1,
+            "options": [
+                { "name": "style", "type": 3, "value": "classic" },
+                { "name": "frame", "type": 3, "value": "DIS", "focused": true },
+            ],
+        }]));
+
+        let focused = data.focused_option().unwrap();
+        assert_eq!(focused.name, "frame");
+        assert_eq!(focused.value, Some(json!("DIS")));
+    }
+
+    #[test]
+    fn no_focused_option_outside_autocomplete() {
+        let data = data(json!([{ "name": "frame", "type": 3, "value": "BTN" }]));
+
+        assert!(data.focused_option().is_none());
+    }
+}
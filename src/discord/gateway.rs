@@ -1,150 +1,249 @@
+use crate::db::session_events::SessionEvent;
+use crate::db::session_limits::SessionLimit;
+use crate::discord::cache;
+use crate::discord::compression::ZlibStream;
 use crate::discord::message::handle::handle_message;
+use crate::discord::permissions;
 use crate::discord::message::message::Message;
+use crate::discord::shard::Shard;
 use crate::error::BotError;
 use crate::state;
 use crate::discord::discord::*;
-use futures_util::{SinkExt, StreamExt};
+use futures_util::{Sink, SinkExt, StreamExt};
 use serde_json::json;
-use tokio::time::{Duration, interval};
+use tokio::time::{Duration, Instant, interval_at};
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, frame::coding::CloseCode};
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message as WsMessage};
 
-pub async fn run_gateway(gateway_url: String) -> Result<(), BotError> {
+/// Why a gateway connection ended without an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disconnect {
+    /// Connect again right away, resuming the session when there is one
+    Reconnect,
+    /// Discord dropped the session; identify again after a random 1-5 second wait
+    SessionInvalidated,
+}
+
+pub async fn run_gateway(gateway_url: String, shard: &Shard) -> Result<Disconnect, BotError> {
     let (ws_stream, _) = connect_async(&gateway_url).await?;
     let (mut write, mut read) = ws_stream.split();
 
+    // One inflate context per connection, shared by every binary frame
+    let mut zlib = shard.compress.then(ZlibStream::new);
+
     while let Some(msg) = read.next().await {
-        let msg = msg?;
-
-        if let WsMessage::Text(text) = msg {
-            let event: DiscordEvent = serde_json::from_str(&text)?;
-            state::update_sequence(event.s).await;
-
-            match event.opcode() {
-                Opcode::Hello => {
-                    // Hello - start heartbeat loop
-                    if let Some(d) = event.d {
-                        let interval_ms = d["heartbeat_interval"].as_u64().unwrap();
-
-                        // Try RESUME if we have session_id, otherwise IDENTIFY
-                        let token = state::token().await;
-                        let session_id = state::get_session_id().await;
-                        let sequence = state::get_sequence().await;
-
-                        if let Some(sid) = session_id {
-                            // RESUME - reconnect with existing session
-                            let _ = crate::db::session_events::SessionEvent::log_resume(
-                                &*state::db().await,
-                                sid.clone(),
-                                sequence,
-                            )
-                            .await;
-
-                            let resume = json!({
-                                "op": Opcode::Resume as u8,
-                                "d": {
-                                    "token": token,
-                                    "session_id": sid,
-                                    "seq": sequence
-                                }
-                            });
-
-                            write
-                                .send(WsMessage::Text(resume.to_string().into()))
-                                .await?;
-                        } else {
-                            // IDENTIFY - new session
-                            let _ = crate::db::session_events::SessionEvent::log_identify(
-                                &*state::db().await,
-                            )
-                            .await;
-
-                            let payload = json!({
-                                "op": 2,
-                                "d": {
-                                    "token": token,
-                                    "intents": 33280, // GUILDS (1 << 0) + GUILD_MESSAGES (1 << 9) + MESSAGE_CONTENT (1 << 15)
-                                    "properties": {
-                                        "os": "linux",
-                                        "browser": "discord-bot",
-                                        "device": "discord-bot"
-                                    }
-                                }
-                            });
-
-                            write
-                                .send(WsMessage::Text(payload.to_string().into()))
-                                .await?;
+        let Some(text) = decode_frame(msg?, &mut zlib)? else {
+            continue;
+        };
+
+        let event: DiscordEvent = serde_json::from_str(&text)?;
+        state::update_sequence(shard.id, event.s).await;
+
+        // Nothing is expected before Hello, which starts the heartbeat loop
+        let (Opcode::Hello, Some(d)) = (event.opcode(), event.d) else {
+            continue;
+        };
+
+        let interval_ms = d["heartbeat_interval"]
+            .as_u64()
+            .ok_or_else(|| BotError::new("gateway_hello_without_interval"))?;
+
+        identify_or_resume(&mut write, shard).await?;
+
+        // First heartbeat goes out after `interval * jitter`, as Discord requires
+        let heartbeat_interval = Duration::from_millis(interval_ms);
+        let jitter = heartbeat_interval.mul_f64(rand::random::<f64>());
+        let mut heartbeat_timer = interval_at(Instant::now() + jitter, heartbeat_interval);
+
+        // Cleared on every heartbeat, set again by HeartbeatAck
+        let mut ack_received = true;
+
+        // Handle both heartbeats and messages
+        loop {
+            tokio::select! {
+                _ = heartbeat_timer.tick() => {
+                    if !ack_received {
+                        // Zombied connection - no ACK since the previous heartbeat
+                        eprintln!("[GATEWAY] Heartbeat ACK missed, reconnecting to resume session");
+                        close_for_resume(&mut write).await;
+                        return Ok(Disconnect::Reconnect);
+                    }
+
+                    ack_received = false;
+
+                    // A failed write means the socket is gone, run_shard reconnects
+                    send_heartbeat(&mut write, shard.id).await?;
+
+                    // Log heartbeat to MongoDB
+                    let _ = state::log_heartbeat().await;
+                }
+                Some(msg_result) = read.next() => {
+                    let Some(text) = decode_frame(msg_result?, &mut zlib)? else {
+                        continue;
+                    };
+
+                    let event: DiscordEvent = serde_json::from_str(&text)?;
+                    state::update_sequence(shard.id, event.s).await;
+
+                    match event.opcode() {
+                        Opcode::Dispatch => {
+                            // Dispatch
+                            handle_dispatch_event(event, shard).await?;
+                        }
+                        Opcode::Heartbeat => {
+                            // Discord requests an immediate heartbeat
+                            send_heartbeat(&mut write, shard.id).await?;
                         }
+                        Opcode::Reconnect => {
+                            // Discord asks us to reconnect and resume
+                            eprintln!("[GATEWAY] Reconnect requested by Discord");
+                            close_for_resume(&mut write).await;
+                            return Ok(Disconnect::Reconnect);
+                        }
+                        Opcode::InvalidSession => {
+                            // `d` tells whether the session can still be resumed
+                            let resumable = event.d.as_ref().and_then(|d| d.as_bool()).unwrap_or(false);
+
+                            let _ = SessionEvent::log_invalid_session(
+                                &*state::db().await
+                            ).await;
+
+                            eprintln!("[GATEWAY] Session invalidated, reconnecting");
+                            close_for_resume(&mut write).await;
 
-                        // Start heartbeat timer
-                        let mut heartbeat_timer = interval(Duration::from_millis(interval_ms));
-                        heartbeat_timer.tick().await; // First tick immediately
-
-                        // Handle both heartbeats and messages
-                        loop {
-                            tokio::select! {
-                                _ = heartbeat_timer.tick() => {
-                                    let seq = state::get_sequence().await;
-                                    let heartbeat = json!({
-                                        "op": Opcode::Heartbeat as u8,
-                                        "d": seq
-                                    });
-
-                                    if write.send(WsMessage::Text(heartbeat.to_string().into())).await.is_err() {
-                                        break;
-                                    }
-
-                                    // Log heartbeat to MongoDB
-                                    let _ = state::log_heartbeat().await;
-                                }
-                                Some(msg_result) = read.next() => {
-                                    match msg_result {
-                                        Ok(WsMessage::Text(text)) => {
-                                            let event: DiscordEvent = serde_json::from_str(&text)?;
-                                            state::update_sequence(event.s).await;
-
-                                            match event.opcode() {
-                                                Opcode::Dispatch => {
-                                                    // Dispatch
-                                                    handle_dispatch_event(event).await?;
-                                                }
-                                                Opcode::InvalidSession => {
-                                                    // Invalid Session - need to re-identify
-                                                    let _ = crate::db::session_events::SessionEvent::log_invalid_session(
-                                                        &*state::db().await
-                                                    ).await;
-                                                    state::clear_session().await;
-                                                    return Ok(());
-                                                }
-                                                Opcode::HeartbeatAck => {
-                                                    // Heartbeat ACK - silent
-                                                }
-                                                _ => {}
-                                            }
-                                        }
-                                        Ok(_) => {}
-                                        Err(e) => return Err(e.into()),
-                                    }
-                                }
+                            if resumable {
+                                return Ok(Disconnect::Reconnect);
                             }
+
+                            // run_shard waits before identifying again, while no heartbeats are due
+                            state::clear_session(shard.id).await;
+                            return Ok(Disconnect::SessionInvalidated);
+                        }
+                        Opcode::HeartbeatAck => {
+                            ack_received = true;
                         }
+                        _ => {}
                     }
                 }
-                _ => {}
             }
         }
     }
 
+    // Discord closed the socket
+    Ok(Disconnect::Reconnect)
+}
+
+/// Extract the JSON payload from a gateway frame, inflating zlib-stream binary frames
+fn decode_frame(
+    msg: WsMessage,
+    zlib: &mut Option<ZlibStream>,
+) -> Result<Option<String>, BotError> {
+    match (msg, zlib) {
+        (WsMessage::Text(text), _) => Ok(Some(text.as_str().to_owned())),
+        (WsMessage::Binary(data), Some(zlib)) => zlib.push(&data),
+        _ => Ok(None),
+    }
+}
+
+/// Send RESUME if we have a session, otherwise IDENTIFY
+async fn identify_or_resume<S>(write: &mut S, shard: &Shard) -> Result<(), BotError>
+where
+    S: Sink<WsMessage, Error = tokio_tungstenite::tungstenite::Error> + Unpin,
+{
+    let token = state::token().await;
+    let session_id = state::get_session_id(shard.id).await;
+    let sequence = state::get_sequence(shard.id).await;
+
+    if let Some(sid) = session_id {
+        // RESUME - reconnect with existing session
+        let _ = SessionEvent::log_resume(
+            &*state::db().await,
+            sid.clone(),
+            sequence,
+        )
+        .await;
+
+        let resume = json!({
+            "op": Opcode::Resume as u8,
+            "d": {
+                "token": token,
+                "session_id": sid,
+                "seq": sequence
+            }
+        });
+
+        write
+            .send(WsMessage::Text(resume.to_string().into()))
+            .await?;
+    } else {
+        // IDENTIFY - new session, run_shard already waited for the daily budget and the bucket slot
+        let _ = SessionEvent::log_identify(&*state::db().await).await;
+        let _ = SessionLimit::consume(&*state::db().await).await;
+
+        let payload = json!({
+            "op": 2,
+            "d": {
+                "token": token,
+                "intents": 33281, // GUILDS (1 << 0) + GUILD_MESSAGES (1 << 9) + MESSAGE_CONTENT (1 << 15)
+                "shard": [shard.id, shard.total],
+                "properties": {
+                    "os": "linux",
+                    "browser": "discord-bot",
+                    "device": "discord-bot"
+                }
+            }
+        });
+
+        write
+            .send(WsMessage::Text(payload.to_string().into()))
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn send_heartbeat<S>(write: &mut S, shard_id: u32) -> Result<(), BotError>
+where
+    S: Sink<WsMessage, Error = tokio_tungstenite::tungstenite::Error> + Unpin,
+{
+    let heartbeat = json!({
+        "op": Opcode::Heartbeat as u8,
+        "d": state::get_sequence(shard_id).await
+    });
+
+    write
+        .send(WsMessage::Text(heartbeat.to_string().into()))
+        .await?;
     Ok(())
 }
 
-async fn handle_dispatch_event(event: DiscordEvent) -> Result<(), BotError> {
+/// Close with a non-1000 code so the session stays resumable
+async fn close_for_resume<S>(write: &mut S)
+where
+    S: Sink<WsMessage, Error = tokio_tungstenite::tungstenite::Error> + Unpin,
+{
+    let frame = CloseFrame {
+        code: CloseCode::Library(4000),
+        reason: "reconnect".into(),
+    };
+    let _ = write.send(WsMessage::Close(Some(frame))).await;
+}
+
+async fn handle_dispatch_event(event: DiscordEvent, shard: &Shard) -> Result<(), BotError> {
     match event.event_type() {
         EventType::Ready => {
             if let Some(d) = event.d {
                 if let Some(session_id) = d["session_id"].as_str() {
-                    state::set_session_id(session_id.to_string()).await;
-                    let _ = crate::db::session_events::SessionEvent::log_ready(
+                    // RESUME must go to resume_gateway_url rather than the generic gateway
+                    let resume_gateway_url = d["resume_gateway_url"].as_str().map(String::from);
+                    state::set_session(
+                        shard.id,
+                        shard.total,
+                        session_id.to_string(),
+                        resume_gateway_url,
+                    )
+                    .await;
+                    let _ = SessionEvent::log_ready(
                         &*state::db().await,
                         session_id.to_string(),
                     )
@@ -160,16 +259,15 @@ async fn handle_dispatch_event(event: DiscordEvent) -> Result<(), BotError> {
         }
         EventType::Resumed => {
             // Session resumed successfully
-            let _ = crate::db::session_events::SessionEvent::log_resumed(&*state::db().await).await;
+            let _ = SessionEvent::log_resumed(&*state::db().await).await;
         }
         EventType::InteractionCreate => {
-            if let Some(d) = event.d {
-                if let Ok(interaction) = serde_json::from_value::<Interaction>(d) {
-                    if let Err(e) = crate::commands::handle_interaction(interaction).await {
-                        eprintln!("[ERROR] Failed to handle interaction:");
-                        e.print_tree();
-                    }
-                }
+            if let Some(d) = event.d
+                && let Ok(interaction) = serde_json::from_value::<Interaction>(d)
+                && let Err(e) = crate::commands::handle_interaction(interaction).await
+            {
+                eprintln!("[ERROR] Failed to handle interaction:");
+                e.print_tree();
             }
         }
         EventType::MessageCreate => {
@@ -184,6 +282,25 @@ async fn handle_dispatch_event(event: DiscordEvent) -> Result<(), BotError> {
                 }
             }
         }
+        EventType::GuildCreate
+        | EventType::GuildUpdate
+        | EventType::GuildDelete
+        | EventType::ChannelCreate
+        | EventType::ChannelUpdate
+        | EventType::ChannelDelete
+        | EventType::ThreadCreate
+        | EventType::ThreadUpdate
+        | EventType::ThreadDelete
+        | EventType::GuildRoleCreate
+        | EventType::GuildRoleUpdate
+        | EventType::GuildRoleDelete
+        | EventType::GuildMemberUpdate => {
+            if let Some(d) = &event.d {
+                // Cache first, so recomputed permissions already see the change
+                cache::handle_event(event.event_type(), d).await;
+                permissions::handle_event(event.event_type(), d).await;
+            }
+        }
         EventType::Unknown => {}
     }
     Ok(())
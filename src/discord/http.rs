@@ -0,0 +1,331 @@
+use crate::commands::SlashCommand;
+use crate::discord::discord::*;
+use crate::discord::message::message::Message;
+use crate::discord::message::send::MessageSend;
+use crate::discord::rate_limiter::{RateLimiter, Route};
+use crate::error::BotError;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+pub const DEFAULT_BASE_URL: &str = "https://discord.com/api/v10";
+
+/// Discord REST client: owns the token, HTTP client, rate limiter and base URL
+pub struct DiscordHttp {
+    token: String,
+    client: Client,
+    limiter: Arc<RateLimiter>,
+    base_url: String,
+}
+
+impl DiscordHttp {
+    pub fn new(token: String, client: Client, limiter: Arc<RateLimiter>, base_url: String) -> Self {
+        Self {
+            token,
+            client,
+            limiter,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn builder(&self, method: &Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method.clone(), format!("{}{}", self.base_url, path))
+            .header("Authorization", format!("Bot {}", self.token))
+    }
+
+    /// Send a request through the rate limiter and decode Discord errors
+    async fn execute<F>(&self, method: Method, path: &str, build: F) -> Result<Response, BotError>
+    where
+        F: Fn(RequestBuilder) -> Result<RequestBuilder, BotError>,
+    {
+        let route = Route::new(method.clone(), path);
+
+        let response = self
+            .limiter
+            .send(&route, || build(self.builder(&method, path)))
+            .await?;
+
+        if response.status().is_success() {
+            return Ok(response);
+        }
+
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+
+        let key = match status {
+            StatusCode::FORBIDDEN => "discord_forbidden",
+            StatusCode::NOT_FOUND => "discord_not_found",
+            _ => "discord_api_error",
+        };
+
+        if let Ok(discord_err) = serde_json::from_str::<DiscordErrorResponse>(&error_text) {
+            return Err(BotError::new(key).push_str(format!("{} {}: {}", method, path, discord_err)));
+        }
+
+        Err(BotError::new("http_error")
+            .push_str(format!("{} {}: {} - {}", method, path, status, error_text)))
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, BotError> {
+        let response = self.execute(Method::GET, path, Ok).await?;
+        Ok(response.json().await?)
+    }
+
+    async fn send_json<B, T>(&self, method: Method, path: &str, body: &B) -> Result<T, BotError>
+    where
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        let response = self
+            .execute(method, path, |request| Ok(request.json(body)))
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// Send a message payload, switching to multipart when it carries files
+    async fn send_message(
+        &self,
+        method: Method,
+        path: &str,
+        message: &MessageSend,
+    ) -> Result<Message, BotError> {
+        let Some(attachments) = &message.attachments else {
+            return self.send_json(method, path, message).await;
+        };
+
+        let response = self
+            .execute(method, path, |request| {
+                let mut form = Form::new().text("payload_json", serde_json::to_string(message)?);
+
+                for (idx, (filename, data)) in attachments.iter().enumerate() {
+                    let part = Part::bytes(data.clone())
+                        .file_name(filename.clone())
+                        .mime_str("application/octet-stream")?;
+                    form = form.part(format!("files[{}]", idx), part);
+                }
+
+                Ok(request.multipart(form))
+            })
+            .await?;
+
+        Ok(response.json().await?)
+    }
+
+    /// GET /gateway/bot
+    pub async fn get_gateway_bot(&self) -> Result<GatewayBotInfo, BotError> {
+        self.get("/gateway/bot").await
+    }
+
+    /// GET /oauth2/applications/@me
+    pub async fn get_current_application(&self) -> Result<ApplicationInfo, BotError> {
+        self.get("/oauth2/applications/@me").await
+    }
+
+    /// GET /applications/{application.id}/commands
+    pub async fn get_global_commands(
+        &self,
+        application_id: &str,
+    ) -> Result<Vec<serde_json::Value>, BotError> {
+        self.get(&format!("/applications/{}/commands", application_id))
+            .await
+    }
+
+    /// PUT /applications/{application.id}/commands
+    pub async fn bulk_overwrite_global_commands(
+        &self,
+        application_id: &str,
+        commands: &[SlashCommand],
+    ) -> Result<Vec<serde_json::Value>, BotError> {
+        self.send_json(
+            Method::PUT,
+            &format!("/applications/{}/commands", application_id),
+            commands,
+        )
+        .await
+    }
+
+    /// GET /applications/{application.id}/guilds/{guild.id}/commands
+    pub async fn get_guild_commands(
+        &self,
+        application_id: &str,
+        guild_id: &str,
+    ) -> Result<Vec<serde_json::Value>, BotError> {
+        self.get(&format!(
+            "/applications/{}/guilds/{}/commands",
+            application_id, guild_id
+        ))
+        .await
+    }
+
+    /// PUT /applications/{application.id}/guilds/{guild.id}/commands
+    pub async fn bulk_overwrite_guild_commands(
+        &self,
+        application_id: &str,
+        guild_id: &str,
+        commands: &[SlashCommand],
+    ) -> Result<Vec<serde_json::Value>, BotError> {
+        self.send_json(
+            Method::PUT,
+            &format!("/applications/{}/guilds/{}/commands", application_id, guild_id),
+            commands,
+        )
+        .await
+    }
+
+    /// POST /interactions/{interaction.id}/{interaction.token}/callback
+    pub async fn create_interaction_response<R: Serialize>(
+        &self,
+        interaction_id: &str,
+        interaction_token: &str,
+        response: &R,
+    ) -> Result<(), BotError> {
+        let path = format!("/interactions/{}/{}/callback", interaction_id, interaction_token);
+        self.execute(Method::POST, &path, |request| Ok(request.json(response)))
+            .await?;
+        Ok(())
+    }
+
+    /// PATCH /webhooks/{application.id}/{interaction.token}/messages/@original
+    pub async fn edit_original_interaction_response(
+        &self,
+        application_id: &str,
+        interaction_token: &str,
+        message: &MessageSend,
+    ) -> Result<Message, BotError> {
+        let path = format!(
+            "/webhooks/{}/{}/messages/@original",
+            application_id, interaction_token
+        );
+        self.send_message(Method::PATCH, &path, message).await
+    }
+
+    /// GET /channels/{channel.id}
+    pub async fn get_channel(&self, channel_id: &str) -> Result<Channel, BotError> {
+        self.get(&format!("/channels/{}", channel_id)).await
+    }
+
+    /// GET /guilds/{guild.id}/roles
+    pub async fn get_guild_roles(&self, guild_id: &str) -> Result<Vec<Role>, BotError> {
+        self.get(&format!("/guilds/{}/roles", guild_id)).await
+    }
+
+    /// GET /guilds/{guild.id}/members/{user.id}
+    pub async fn get_guild_member(
+        &self,
+        guild_id: &str,
+        user_id: &str,
+    ) -> Result<GuildMember, BotError> {
+        self.get(&format!("/guilds/{}/members/{}", guild_id, user_id))
+            .await
+    }
+
+    /// POST /channels/{channel.id}/messages
+    pub async fn create_message(
+        &self,
+        channel_id: &str,
+        message: &MessageSend,
+    ) -> Result<Message, BotError> {
+        let path = format!("/channels/{}/messages", channel_id);
+        self.send_message(Method::POST, &path, message).await
+    }
+
+    /// PATCH /channels/{channel.id}/messages/{message.id}
+    pub async fn edit_message(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        message: &MessageSend,
+    ) -> Result<Message, BotError> {
+        let path = format!("/channels/{}/messages/{}", channel_id, message_id);
+        self.send_message(Method::PATCH, &path, message).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// Local stand-in for Discord: answers each connection with the next canned response and
+    /// hands back the request heads it received
+    async fn mock_discord(responses: Vec<String>) -> (DiscordHttp, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/api/v10/", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut head = Vec::new();
+                let mut buf = [0; 1024];
+                while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let read = socket.read(&mut buf).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    head.extend_from_slice(&buf[..read]);
+                }
+                requests.push(String::from_utf8_lossy(&head).into_owned());
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let http = DiscordHttp::new(
+            "test-token".to_string(),
+            Client::new(),
+            Arc::new(RateLimiter::new(50.0)),
+            base_url,
+        );
+        (http, server)
+    }
+
+    fn response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    #[tokio::test]
+    async fn requests_go_to_the_configured_base_url() {
+        let (http, server) = mock_discord(vec![response("200 OK", r#"{"id":"42"}"#)]).await;
+
+        let application = http.get_current_application().await.unwrap();
+        assert_eq!(application.id, "42");
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("GET /api/v10/oauth2/applications/@me HTTP/1.1"));
+        assert!(requests[0].to_lowercase().contains("authorization: bot test-token"));
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_retried() {
+        let limited = r#"{"message":"You are being rate limited.","retry_after":0.01,"global":false}"#;
+        let (http, server) = mock_discord(vec![
+            response("429 Too Many Requests", limited),
+            response("200 OK", r#"{"id":"42"}"#),
+        ])
+        .await;
+
+        assert_eq!(http.get_current_application().await.unwrap().id, "42");
+        assert_eq!(server.await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn error_statuses_map_to_error_keys() {
+        let missing = r#"{"message":"Unknown Channel","code":10003}"#;
+        let (http, _server) = mock_discord(vec![response("404 Not Found", missing)]).await;
+
+        let error = http.get_channel("1").await.unwrap_err();
+        assert_eq!(error.key, "discord_not_found");
+    }
+}
@@ -14,6 +14,9 @@ pub struct Attachment {
     pub id: String,
     pub url: String,
     pub filename: String,
+    /// Bytes; missing on jobs queued before sizes were recorded
+    #[serde(default)]
+    pub size: u64,
 }
 
 pub fn ensure_unique_filenames(mut attachments: Vec<Attachment>) -> Vec<Attachment> {
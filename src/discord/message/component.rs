@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+/// https://discord.com/developers/docs/interactions/message-components#component-object-component-types
+pub const ACTION_ROW: u8 = 1;
+pub const BUTTON: u8 = 2;
+pub const STRING_SELECT: u8 = 3;
+pub const TEXT_INPUT: u8 = 4;
+
+/// https://discord.com/developers/docs/interactions/message-components#button-object-button-styles
+pub const BUTTON_PRIMARY: u8 = 1;
+pub const BUTTON_SECONDARY: u8 = 2;
+
+/// https://discord.com/developers/docs/interactions/message-components#text-input-object-text-input-styles
+pub const TEXT_INPUT_SHORT: u8 = 1;
+
+/// https://discord.com/developers/docs/interactions/message-components#action-rows
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionRow {
+    #[serde(rename = "type")]
+    pub kind: u8,
+    pub components: Vec<Component>,
+}
+
+impl ActionRow {
+    pub fn new(components: Vec<Component>) -> Self {
+        Self {
+            kind: ACTION_ROW,
+            components,
+        }
+    }
+}
+
+/// Button, string select or text input inside an action row
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Component {
+    #[serde(rename = "type")]
+    pub kind: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<SelectOption>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub placeholder: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_values: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_values: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required: Option<bool>,
+    /// Prefilled text of a text input, or the submitted text in a modal submit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+impl Component {
+    pub fn button(custom_id: String, label: &str, style: u8) -> Self {
+        Self {
+            kind: BUTTON,
+            custom_id: Some(custom_id),
+            style: Some(style),
+            label: Some(label.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Select allowing any number of the options, at least one
+    pub fn multi_select(custom_id: String, placeholder: &str, options: Vec<SelectOption>) -> Self {
+        Self {
+            kind: STRING_SELECT,
+            custom_id: Some(custom_id),
+            placeholder: Some(placeholder.to_string()),
+            min_values: Some(1),
+            max_values: Some(options.len() as u8),
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Optional single-line text input for a modal
+    pub fn text_input(custom_id: &str, label: &str, placeholder: &str, value: Option<String>) -> Self {
+        Self {
+            kind: TEXT_INPUT,
+            custom_id: Some(custom_id.to_string()),
+            style: Some(TEXT_INPUT_SHORT),
+            label: Some(label.to_string()),
+            placeholder: Some(placeholder.to_string()),
+            required: Some(false),
+            value,
+            ..Default::default()
+        }
+    }
+}
+
+/// https://discord.com/developers/docs/interactions/message-components#select-menu-object-select-option-structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelectOption {
+    pub label: String,
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl SelectOption {
+    pub fn new(label: &str, value: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            value: value.to_string(),
+            description: None,
+        }
+    }
+}
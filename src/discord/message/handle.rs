@@ -1,17 +1,38 @@
+use crate::assets::{BORDER_VARIANTS, FrameStyle};
+use crate::commands::{self, CommandOption};
+use crate::db::guild_config::GuildConfig;
+use crate::discord::discord::{InteractionData, InteractionTarget, OptionType};
 use crate::discord::message::message::Message;
+use crate::discord::message::send::MessageSend;
 use crate::error::BotError;
 use crate::state;
 use crate::workers::blp::job::ConversionTarget;
-use serde::Serialize;
+use crate::workers::icon::job::JobIcon;
+use crate::workers::rembg::models;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CommandKind {
     Blp,
     Png,
-    Rembg, // includes "rembg" and "bg" aliases
+    Rembg,
     Icon,
 }
 
+impl CommandKind {
+    /// Command name as users type it
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Blp => "blp",
+            Self::Png => "png",
+            Self::Rembg => "rembg",
+            Self::Icon => "icon",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct CommandArgs {
     pub kind: CommandKind,
@@ -20,6 +41,14 @@ pub struct CommandArgs {
     pub zip: bool,
     pub binary: bool,
     pub mask: bool,
+    pub mip_count: Option<u8>,          // BLP mip levels, all when unset
+    pub alpha_depth: Option<u8>,        // BLP alpha bits, 0 or 8, kept when unset
+    pub crop_x: Option<u32>,            // ICON crop offset, centered when unset
+    pub crop_y: Option<u32>,
+    pub output_path: Option<String>,    // ICON archive folder
+    pub frame_style: FrameStyle,        // ICON frame, from the server settings
+    pub variants: Vec<String>,          // ICON border variants, all when empty
+    pub model: Option<String>,          // REMBG model, default when unset
 }
 
 impl Default for CommandArgs {
@@ -31,52 +60,102 @@ impl Default for CommandArgs {
             zip: false,
             binary: false,
             mask: false,
+            mip_count: None,
+            alpha_depth: None,
+            crop_x: None,
+            crop_y: None,
+            output_path: None,
+            frame_style: FrameStyle::default(),
+            variants: Vec::new(),
+            model: None,
         }
     }
 }
 
-pub fn parse_command_args(content: &str, _bot_id: &str) -> Option<CommandArgs> {
-    let mut args = CommandArgs::default();
-
-    let tokens: Vec<&str> = content
-        .trim()
+/// Parse a mention like `@Raft blp 90 zip`, starting from the guild's defaults
+///
+/// Tokens are read against the command's slash options, so a mention takes the same settings:
+/// `name:value` sets any of them, the bare name of a boolean option turns it on and a bare
+/// number goes to the first integer option whose range fits it.
+pub fn parse_command_args(content: &str, defaults: CommandArgs) -> Option<CommandArgs> {
+    let mut tokens = content
         .split_whitespace()
-        .filter(|t| !t.starts_with('<')) // пропускаем упоминания
-        .collect();
+        .filter(|t| !t.starts_with('<')); // пропускаем упоминания
+
+    let (kind, command) = commands::command_for_alias(tokens.next()?)?;
 
-    if tokens.is_empty() {
-        return None;
+    // Later tokens override earlier ones, like repeating an option would
+    let mut values: HashMap<&str, Value> = HashMap::new();
+    for token in tokens {
+        if let Some((name, value)) = mention_option(&command.options, token) {
+            values.insert(name, value);
+        }
     }
 
-    args.kind = match tokens[0] {
-        "blp" => CommandKind::Blp,
-        "png" => CommandKind::Png,
-        "rembg" | "bg" => CommandKind::Rembg,
-        "icon" => CommandKind::Icon,
-        _ => return None,
-    };
+    Some(command_args(kind, |name| values.get(name), defaults))
+}
 
-    for &tok in &tokens[1..] {
-        match tok {
-            "zip" => args.zip = true,
-            "binary" => args.binary = true,
-            "mask" => args.mask = true,
-            _ => {
-                if let Ok(num) = tok.parse::<u16>() {
-                    match args.kind {
-                        CommandKind::Blp if (1..=100).contains(&num) => args.quality = num as u8,
-                        CommandKind::Rembg if num <= 255 => args.threshold = num as u8,
-                        _ => {}
-                    }
-                }
-            }
-        }
+/// Option a mention token sets, with its value
+fn mention_option<'a>(options: &'a [CommandOption], token: &str) -> Option<(&'a str, Value)> {
+    if let Some((name, raw)) = token.split_once(':') {
+        let option = options.iter().find(|o| o.name.eq_ignore_ascii_case(name))?;
+        return Some((option.name.as_str(), option.mention_value(raw)?));
+    }
+
+    let flag = options
+        .iter()
+        .find(|o| o.option_type == OptionType::Boolean && o.name.eq_ignore_ascii_case(token));
+    if let Some(option) = flag {
+        return Some((option.name.as_str(), Value::Bool(true)));
     }
 
-    Some(args)
+    let number = token.parse::<i64>().ok()?;
+    options
+        .iter()
+        .find(|o| o.option_type == OptionType::Integer && o.accepts(number))
+        .map(|option| (option.name.as_str(), Value::from(number)))
+}
+
+/// Build command arguments from slash command options, falling back to the guild's defaults
+pub fn interaction_command_args(
+    kind: CommandKind,
+    data: &InteractionData,
+    defaults: CommandArgs,
+) -> CommandArgs {
+    command_args(kind, |name| data.get_option(name), defaults)
 }
 
-pub async fn handle_message(message: Message) -> Result<(), BotError> {
+/// Apply option values by name on top of the defaults, for slash commands and mentions alike
+fn command_args<'a>(
+    kind: CommandKind,
+    option: impl Fn(&str) -> Option<&'a Value>,
+    defaults: CommandArgs,
+) -> CommandArgs {
+    let integer = |name: &str| option(name).and_then(Value::as_i64);
+    let boolean = |name: &str| option(name).and_then(Value::as_bool);
+    let string = |name: &str| option(name).and_then(Value::as_str);
+
+    CommandArgs {
+        kind,
+        quality: integer("quality").map_or(defaults.quality, |q| q.clamp(1, 100) as u8),
+        threshold: integer("threshold").map_or(defaults.threshold, |t| t.clamp(0, 255) as u8),
+        zip: boolean("zip").unwrap_or(defaults.zip),
+        binary: boolean("binary").unwrap_or(defaults.binary),
+        mask: boolean("mask").unwrap_or(defaults.mask),
+        output_path: string("path").map(JobIcon::normalize_output_path),
+        variants: string("frame")
+            .map(str::to_uppercase)
+            .filter(|frame| BORDER_VARIANTS.contains(&frame.as_str()))
+            .into_iter()
+            .collect(),
+        model: string("model")
+            .filter(|model| models::available_models().iter().any(|m| m == model))
+            .map(String::from),
+        ..defaults
+    }
+}
+
+pub async fn handle_message(mut message: Message) -> Result<(), BotError> {
     if message.author.bot.unwrap_or(false) {
         return Ok(());
     }
@@ -86,22 +165,104 @@ pub async fn handle_message(message: Message) -> Result<(), BotError> {
         return Ok(());
     }
 
-    let Some(args) = parse_command_args(&message.content, &bot_user_id) else {
+    let config = GuildConfig::for_guild(&*state::db().await, message.guild_id.as_deref()).await?;
+    // Stay quiet where the bot is off, replying there would be just as unwelcome
+    if !config.allows_channel(&message.channel_id) {
+        return Ok(());
+    }
+
+    let Some(args) = parse_command_args(&message.content, config.command_defaults()) else {
         return Ok(());
     };
 
+    if let Some(refusal) = config.admit(args.kind, &mut message) {
+        MessageSend {
+            content: Some(refusal),
+            message_reference: message.reply_reference(),
+            components: None,
+            attachments: None,
+        }
+        .create(&message.channel_id)
+        .await?;
+        return Ok(());
+    }
+
+    enqueue_job(message, args, None).await
+}
+
+/// Queue the job for a parsed command on its worker pool
+pub async fn enqueue_job(
+    message: Message,
+    args: CommandArgs,
+    interaction: Option<InteractionTarget>,
+) -> Result<(), BotError> {
     match args.kind {
         CommandKind::Blp => {
-            crate::workers::blp::handle::handle(message, ConversionTarget::BLP, args).await
+            crate::workers::blp::handle::handle(message, ConversionTarget::Blp, args, interaction)
+                .await
         }
         CommandKind::Png => {
-            crate::workers::blp::handle::handle(message, ConversionTarget::PNG, args).await
+            crate::workers::blp::handle::handle(message, ConversionTarget::Png, args, interaction)
+                .await
         }
         CommandKind::Rembg => {
-            crate::workers::rembg::handle::handle(message, &args).await //
+            crate::workers::rembg::handle::handle(message, &args, interaction).await
         }
         CommandKind::Icon => {
-            crate::workers::icon::handle::handle(message, &args).await
+            crate::workers::icon::handle::handle(message, &args, interaction).await
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> Vec<CommandOption> {
+        vec![
+            CommandOption::new(OptionType::Integer, "quality", "JPEG quality").range(1, 100),
+            CommandOption::new(OptionType::Integer, "threshold", "Alpha cutoff").range(101, 255),
+            CommandOption::new(OptionType::Boolean, "zip", "Send one archive"),
+            CommandOption::new(OptionType::String, "format", "Output format")
+                .choice("PNG", "png")
+                .choice("BLP", "blp"),
+        ]
+    }
+
+    #[test]
+    fn named_values_are_parsed_by_option_type() {
+        let options = options();
+
+        assert_eq!(mention_option(&options, "quality:80"), Some(("quality", Value::from(80))));
+        assert_eq!(mention_option(&options, "ZIP:off"), Some(("zip", Value::Bool(false))));
+        assert_eq!(mention_option(&options, "format:blp"), Some(("format", Value::from("blp"))));
+    }
+
+    #[test]
+    fn invalid_named_values_are_rejected() {
+        let options = options();
+
+        assert_eq!(mention_option(&options, "quality:0"), None);
+        assert_eq!(mention_option(&options, "quality:high"), None);
+        assert_eq!(mention_option(&options, "zip:maybe"), None);
+        assert_eq!(mention_option(&options, "format:gif"), None);
+        assert_eq!(mention_option(&options, "unknown:1"), None);
+    }
+
+    #[test]
+    fn bare_option_name_sets_a_flag() {
+        let options = options();
+
+        assert_eq!(mention_option(&options, "zip"), Some(("zip", Value::Bool(true))));
+        assert_eq!(mention_option(&options, "format"), None);
+    }
+
+    #[test]
+    fn bare_numbers_go_to_the_first_integer_option_in_range() {
+        let options = options();
+
+        assert_eq!(mention_option(&options, "90"), Some(("quality", Value::from(90))));
+        assert_eq!(mention_option(&options, "200"), Some(("threshold", Value::from(200))));
+        assert_eq!(mention_option(&options, "300"), None);
+    }
+}
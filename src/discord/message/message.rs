@@ -1,3 +1,4 @@
+use crate::discord::discord::GuildMember;
 use crate::discord::message::attachment::Attachment;
 use serde::{Deserialize, Serialize};
 
@@ -7,12 +8,35 @@ pub struct Message {
     pub id: String,
     pub author: User,
     pub channel_id: String,
+    /// None in DMs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guild_id: Option<String>,
+    /// Author's roles in the guild, None in DMs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub member: Option<GuildMember>,
     pub content: String,
     #[serde(default)]
     pub attachments: Vec<Attachment>,
     #[serde(default)]
     pub mentions: Vec<User>,
     pub message_reference: Option<MessageReference>,
+    /// Set on interaction responses, which only the interaction webhook can edit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_id: Option<String>,
+}
+
+impl Message {
+    /// Reference for replying to this message; none for synthetic slash command messages
+    pub fn reply_reference(&self) -> Option<MessageReference> {
+        if self.id.is_empty() {
+            return None;
+        }
+
+        Some(MessageReference {
+            message_id: Some(self.id.clone()),
+            ..Default::default()
+        })
+    }
 }
 
 /// https://discord.com/developers/docs/resources/message#message-reference-structure
@@ -1,4 +1,6 @@
+#[allow(clippy::module_inception)]
 pub mod message;
 pub mod handle;
 pub mod send;
-pub mod attachment;
\ No newline at end of file
+pub mod attachment;
+pub mod component;
\ No newline at end of file
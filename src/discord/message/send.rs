@@ -1,7 +1,8 @@
+use crate::discord::discord::InteractionTarget;
+use crate::discord::message::component::ActionRow;
 use crate::discord::message::message::{Message, MessageReference};
 use crate::error::BotError;
 use crate::state;
-use reqwest::Method;
 use serde::{Deserialize, Serialize};
 
 /// https://discord.com/developers/docs/resources/message#create-message
@@ -9,66 +10,62 @@ use serde::{Deserialize, Serialize};
 pub struct MessageSend {
     pub content: Option<String>,
     pub message_reference: Option<MessageReference>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub components: Option<Vec<ActionRow>>,
     #[serde(skip)]
     pub attachments: Option<Vec<(String, Vec<u8>)>>,
 }
 
 impl MessageSend {
-    pub async fn send(&self, method: Method, channel_id: &str, message_id: Option<&str>) -> Result<Message, BotError> {
-        let limiter = state::rate_limiter().await;
-        limiter.acquire().await;
-
-        let client = state::client().await;
-        let token = state::token().await;
-
-        let url = if let Some(msg_id) = message_id {
-            format!(
-                "https://discord.com/api/v10/channels/{}/messages/{}",
-                channel_id, msg_id
-            )
-        } else {
-            format!(
-                "https://discord.com/api/v10/channels/{}/messages",
-                channel_id
-            )
-        };
-
-        let mut request = client
-            .request(method.clone(), &url)
-            .header("Authorization", format!("Bot {}", token));
-
-        if let Some(attachments) = &self.attachments {
-            use reqwest::multipart::{Form, Part};
-
-            let mut form = Form::new();
+    /// Post as a new message in the channel
+    pub async fn create(&self, channel_id: &str) -> Result<Message, BotError> {
+        state::http().await.create_message(channel_id, self).await
+    }
 
-            let payload = serde_json::to_string(self)?;
-            form = form.text("payload_json", payload);
+    /// Replace the content of an existing message
+    pub async fn edit(&self, channel_id: &str, message_id: &str) -> Result<Message, BotError> {
+        state::http()
+            .await
+            .edit_message(channel_id, message_id, self)
+            .await
+    }
 
-            for (idx, (filename, data)) in attachments.iter().enumerate() {
-                let part = Part::bytes(data.clone())
-                    .file_name(filename.clone())
-                    .mime_str("application/octet-stream")?;
-                form = form.part(format!("files[{}]", idx), part);
-            }
+    /// Replace the original (possibly deferred) interaction response
+    pub async fn edit_original(&self, target: &InteractionTarget) -> Result<Message, BotError> {
+        state::http()
+            .await
+            .edit_original_interaction_response(&target.application_id, &target.token, self)
+            .await
+    }
 
-            request = request.multipart(form);
-        } else {
-            request = request
-                .header("Content-Type", "application/json")
-                .json(self);
+    /// Post the first reply of a job: fill in the interaction response, or reply in the channel
+    ///
+    /// Interactions older than their token lifetime get a channel message as well.
+    pub async fn reply(
+        &self,
+        channel_id: &str,
+        interaction: Option<&InteractionTarget>,
+    ) -> Result<Message, BotError> {
+        match interaction.filter(|target| !target.expired()) {
+            Some(target) => self.edit_original(target).await,
+            None => self.create(channel_id).await,
         }
+    }
 
-        let response = request.send().await?;
-
-        let _ = crate::db::rate_limits::RateLimit::update_from_headers(
-            &*state::db().await,
-            format!("{} /channels/{}/messages{}", method, channel_id, if message_id.is_some() { "/{message_id}" } else { "" }),
-            response.headers(),
-        )
-        .await;
-
-        let msg: Message = response.json().await?;
-        Ok(msg)
+    /// Update the reply posted by [`MessageSend::reply`]
+    ///
+    /// An interaction response belongs to the application webhook and can't be edited as a
+    /// channel message, so once its token expired the update is posted as a new message.
+    pub async fn update(
+        &self,
+        channel_id: &str,
+        reply: &Message,
+        interaction: Option<&InteractionTarget>,
+    ) -> Result<Message, BotError> {
+        match interaction.filter(|target| !target.expired()) {
+            Some(target) => self.edit_original(target).await,
+            None if reply.webhook_id.is_some() => self.create(channel_id).await,
+            None => self.edit(channel_id, &reply.id).await,
+        }
     }
 }
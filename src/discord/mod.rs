@@ -1,4 +1,11 @@
 pub mod api;
+pub mod cache;
+pub mod compression;
+#[allow(clippy::module_inception)]
 pub mod discord;
 pub mod gateway;
+pub mod http;
 pub mod message;
+pub mod permissions;
+pub mod rate_limiter;
+pub mod shard;
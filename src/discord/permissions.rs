@@ -0,0 +1,226 @@
+//! Effective permissions of the bot in a channel: roles, then channel overwrites
+
+use crate::discord::cache;
+use crate::discord::discord::{Channel, EventType, PermissionOverwrite, Role};
+use crate::error::BotError;
+use crate::state;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+/// https://discord.com/developers/docs/topics/permissions#permissions-bitwise-permission-flags
+pub const ADMINISTRATOR: u64 = 1 << 3;
+pub const MANAGE_GUILD: u64 = 1 << 5;
+pub const VIEW_CHANNEL: u64 = 1 << 10;
+pub const SEND_MESSAGES: u64 = 1 << 11;
+pub const ATTACH_FILES: u64 = 1 << 15;
+pub const READ_MESSAGE_HISTORY: u64 = 1 << 16;
+
+/// What the bot needs to take images and post results
+pub const REQUIRED: u64 = VIEW_CHANNEL | SEND_MESSAGES | ATTACH_FILES | READ_MESSAGE_HISTORY;
+
+/// https://discord.com/developers/docs/resources/channel#overwrite-object-overwrite-structure
+const OVERWRITE_ROLE: u8 = 0;
+const OVERWRITE_MEMBER: u8 = 1;
+
+struct CachedPermissions {
+    guild_id: String,
+    /// Parent of a thread, its overwrites decide the thread's permissions
+    parent_id: Option<String>,
+    permissions: u64,
+}
+
+/// Computed permissions by channel id, dropped when a gateway event changes their inputs
+static CACHE: Lazy<DashMap<String, CachedPermissions>> = Lazy::new(DashMap::new);
+
+/// Permissions of the bot in a channel.
+///
+/// Fails with `bot_not_in_server` when the bot can't see the channel's guild.
+pub async fn bot_channel_permissions(channel_id: &str) -> Result<u64, BotError> {
+    if let Some(cached) = CACHE.get(channel_id) {
+        return Ok(cached.permissions);
+    }
+
+    let bot_user_id = state::bot_user_id().await;
+    if bot_user_id.is_empty() {
+        return Err(BotError::new("bot_user_id_unavailable"));
+    }
+
+    let channel = match cache::channel(channel_id) {
+        Some(channel) => channel,
+        None => fetch_channel(channel_id).await?,
+    };
+
+    // DMs have no guild and no overwrites, everything the bot needs is allowed
+    let Some(guild_id) = channel.guild_id.clone() else {
+        return Ok(REQUIRED);
+    };
+
+    // Threads have no overwrites of their own and follow the channel they were started in
+    let overwrites = match (&channel.parent_id, channel.is_thread()) {
+        (Some(parent_id), true) => match cache::channel(parent_id) {
+            Some(parent) => parent.permission_overwrites,
+            None => fetch_channel(parent_id).await?.permission_overwrites,
+        },
+        _ => channel.permission_overwrites.clone(),
+    };
+
+    // The owner has every permission regardless of roles
+    let permissions = if cache::guild_owner_id(&guild_id).as_deref() == Some(bot_user_id.as_str()) {
+        u64::MAX
+    } else {
+        let (roles, member_roles) = guild_roles(&guild_id, &bot_user_id).await?;
+        let base = base_permissions(&guild_id, &roles, &member_roles);
+        apply_overwrites(base, &guild_id, &bot_user_id, &member_roles, &overwrites)
+    };
+
+    CACHE.insert(
+        channel_id.to_string(),
+        CachedPermissions {
+            guild_id,
+            parent_id: channel.is_thread().then(|| channel.parent_id.clone()).flatten(),
+            permissions,
+        },
+    );
+
+    Ok(permissions)
+}
+
+/// Whether a member owns the guild or holds an ADMINISTRATOR role.
+///
+/// Only the gateway cache is consulted, members of guilds it doesn't know are not admins.
+pub fn is_guild_admin(guild_id: &str, user_id: &str, member_roles: &[String]) -> bool {
+    if cache::guild_owner_id(guild_id).as_deref() == Some(user_id) {
+        return true;
+    }
+
+    cache::guild_roles(guild_id)
+        .is_some_and(|roles| base_permissions(guild_id, &roles, member_roles) & ADMINISTRATOR != 0)
+}
+
+/// Roles of the guild and the bot's role ids, from the gateway cache when GUILD_CREATE has arrived
+async fn guild_roles(guild_id: &str, bot_user_id: &str) -> Result<(Vec<Role>, Vec<String>), BotError> {
+    if let (Some(roles), Some(member_roles)) = (cache::guild_roles(guild_id), cache::bot_roles(guild_id)) {
+        return Ok((roles, member_roles));
+    }
+
+    let roles = state::http().await.get_guild_roles(guild_id).await?;
+    let member = match state::http().await.get_guild_member(guild_id, bot_user_id).await {
+        Ok(member) => member,
+        Err(e) if e.key == "discord_not_found" => {
+            return Err(BotError::new("bot_not_in_server").push_any(e));
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok((roles, member.roles))
+}
+
+async fn fetch_channel(channel_id: &str) -> Result<Channel, BotError> {
+    match state::http().await.get_channel(channel_id).await {
+        Ok(channel) => Ok(channel),
+        // 403 Forbidden - bot is not in this server
+        Err(e) if e.key == "discord_forbidden" => Err(BotError::new("bot_not_in_server").push_any(e)),
+        Err(e) => Err(BotError::new("channel_fetch_failed").push_any(e)),
+    }
+}
+
+/// Union of @everyone and the member's roles; ADMINISTRATOR grants everything
+fn base_permissions(guild_id: &str, roles: &[Role], member_roles: &[String]) -> u64 {
+    // The @everyone role has the same id as the guild
+    let permissions = roles
+        .iter()
+        .filter(|role| role.id == guild_id || member_roles.contains(&role.id))
+        .fold(0, |acc, role| acc | parse_bits(&role.permissions));
+
+    if permissions & ADMINISTRATOR != 0 {
+        return u64::MAX;
+    }
+
+    permissions
+}
+
+/// Apply channel overwrites in Discord's order: @everyone, then all roles together, then the member
+fn apply_overwrites(
+    base: u64,
+    guild_id: &str,
+    user_id: &str,
+    member_roles: &[String],
+    overwrites: &[PermissionOverwrite],
+) -> u64 {
+    // Administrators ignore overwrites
+    if base & ADMINISTRATOR != 0 {
+        return u64::MAX;
+    }
+
+    let mut permissions = base;
+
+    if let Some(everyone) = overwrites.iter().find(|overwrite| overwrite.id == guild_id) {
+        permissions &= !parse_bits(&everyone.deny);
+        permissions |= parse_bits(&everyone.allow);
+    }
+
+    let (allow, deny) = overwrites
+        .iter()
+        .filter(|overwrite| overwrite.kind == OVERWRITE_ROLE && member_roles.contains(&overwrite.id))
+        .fold((0, 0), |(allow, deny), overwrite| {
+            (allow | parse_bits(&overwrite.allow), deny | parse_bits(&overwrite.deny))
+        });
+    permissions &= !deny;
+    permissions |= allow;
+
+    if let Some(member) = overwrites
+        .iter()
+        .find(|overwrite| overwrite.kind == OVERWRITE_MEMBER && overwrite.id == user_id)
+    {
+        permissions &= !parse_bits(&member.deny);
+        permissions |= parse_bits(&member.allow);
+    }
+
+    permissions
+}
+
+/// Discord sends permission bit sets as decimal strings
+fn parse_bits(bits: &str) -> u64 {
+    bits.parse().unwrap_or(0)
+}
+
+/// Drop cached permissions a dispatch event may have changed
+pub async fn handle_event(event_type: EventType, d: &Value) {
+    match event_type {
+        EventType::ChannelUpdate
+        | EventType::ChannelDelete
+        | EventType::ThreadUpdate
+        | EventType::ThreadDelete => {
+            if let Some(channel_id) = d["id"].as_str() {
+                CACHE.retain(|id, cached| {
+                    id != channel_id && cached.parent_id.as_deref() != Some(channel_id)
+                });
+            }
+        }
+        EventType::GuildRoleUpdate | EventType::GuildRoleDelete => {
+            if let Some(guild_id) = d["guild_id"].as_str() {
+                invalidate_guild(guild_id);
+            }
+        }
+        EventType::GuildMemberUpdate => {
+            // Only the bot's own roles matter here
+            let bot_user_id = state::bot_user_id().await;
+            if d["user"]["id"].as_str() == Some(bot_user_id.as_str())
+                && let Some(guild_id) = d["guild_id"].as_str()
+            {
+                invalidate_guild(guild_id);
+            }
+        }
+        EventType::GuildCreate | EventType::GuildUpdate | EventType::GuildDelete => {
+            if let Some(guild_id) = d["id"].as_str() {
+                invalidate_guild(guild_id);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn invalidate_guild(guild_id: &str) {
+    CACHE.retain(|_, cached| cached.guild_id != guild_id);
+}
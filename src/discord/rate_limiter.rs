@@ -0,0 +1,360 @@
+use crate::db::rate_limits::RateLimit;
+use crate::discord::discord::DiscordErrorResponse;
+use crate::error::BotError;
+use crate::state;
+use dashmap::DashMap;
+use reqwest::header::HeaderMap;
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// How many times a request is retried after HTTP 429 before giving up
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Discord REST route: HTTP method plus API path (without the `/api/v10` prefix)
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub method: Method,
+    pub path: String,
+}
+
+impl Route {
+    pub fn new(method: Method, path: impl Into<String>) -> Self {
+        Self {
+            method,
+            path: path.into(),
+        }
+    }
+
+    /// Route template with ids and tokens replaced, e.g. `PATCH /channels/{channel_id}/messages/{id}`
+    pub fn key(&self) -> String {
+        self.split().0
+    }
+
+    /// Major parameter (channel, guild or webhook) the bucket is scoped to
+    pub fn major(&self) -> String {
+        self.split().1
+    }
+
+    fn split(&self) -> (String, String) {
+        let path = self.path.split('?').next().unwrap_or_default();
+
+        let mut template = Vec::new();
+        let mut major = String::new();
+        let mut previous: [&str; 2] = ["", ""];
+
+        for segment in path.trim_start_matches('/').split('/') {
+            let is_id = !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit());
+            let previous_is_id =
+                !previous[1].is_empty() && previous[1].chars().all(|c| c.is_ascii_digit());
+
+            let part = match previous[1] {
+                "channels" | "guilds" | "webhooks" if is_id && major.is_empty() => {
+                    major = segment.to_string();
+                    format!("{{{}_id}}", previous[1].trim_end_matches('s'))
+                }
+                _ if is_id => "{id}".to_string(),
+                _ if previous_is_id && matches!(previous[0], "webhooks" | "interactions") => {
+                    // Webhook tokens belong to the major parameter, interaction tokens do not
+                    if previous[0] == "webhooks" {
+                        major.push('/');
+                        major.push_str(segment);
+                    }
+                    "{token}".to_string()
+                }
+                _ => segment.to_string(),
+            };
+
+            template.push(part);
+            previous = [previous[1], segment];
+        }
+
+        (format!("{} /{}", self.method, template.join("/")), major)
+    }
+}
+
+/// Per-bucket state learned from `x-ratelimit-*` headers
+#[derive(Debug)]
+struct Bucket {
+    limit: u32,
+    remaining: u32,
+    reset_at: Option<Instant>,
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        // Unknown bucket: let requests through until Discord tells us the real limit
+        Self {
+            limit: 1,
+            remaining: 1,
+            reset_at: None,
+        }
+    }
+}
+
+/// Token bucket used as a global requests-per-second throttle
+struct TokenBucket {
+    tokens: Mutex<f64>,
+    max_tokens: f64,
+    refill_rate: f64, // tokens per second
+    last_refill: Mutex<Instant>,
+}
+
+impl TokenBucket {
+    fn new(requests_per_second: f64) -> Self {
+        Self {
+            tokens: Mutex::new(requests_per_second),
+            max_tokens: requests_per_second,
+            refill_rate: requests_per_second,
+            last_refill: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn acquire(&self) {
+        loop {
+            // Refill tokens based on elapsed time
+            let now = Instant::now();
+            let mut last_refill = self.last_refill.lock().await;
+            let elapsed = now.duration_since(*last_refill).as_secs_f64();
+
+            let mut tokens = self.tokens.lock().await;
+            let new_tokens = (*tokens + elapsed * self.refill_rate).min(self.max_tokens);
+            *tokens = new_tokens;
+            *last_refill = now;
+
+            // Try to consume one token
+            if *tokens >= 1.0 {
+                *tokens -= 1.0;
+                return;
+            }
+
+            // Not enough tokens, wait for refill
+            drop(tokens);
+            drop(last_refill);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+/// Discord REST rate limiter.
+///
+/// Requests are grouped into buckets keyed by `x-ratelimit-bucket` plus the major
+/// parameter of the route. A bucket with no remaining requests blocks callers until
+/// it resets. The global limit is enforced by a token bucket and by the deadline
+/// from global 429 responses.
+pub(crate) struct RateLimiter {
+    global: TokenBucket,
+    global_until: Mutex<Option<Instant>>,
+    /// Route template → bucket hash reported by Discord
+    routes: DashMap<String, String>,
+    /// `bucket:major` → bucket state
+    buckets: DashMap<String, Arc<Mutex<Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64) -> Self {
+        Self {
+            global: TokenBucket::new(requests_per_second),
+            global_until: Mutex::new(None),
+            routes: DashMap::new(),
+            buckets: DashMap::new(),
+        }
+    }
+
+    fn bucket(&self, route: &Route) -> Arc<Mutex<Bucket>> {
+        let key = route.key();
+        let hash = self
+            .routes
+            .get(&key)
+            .map(|h| h.value().clone())
+            .unwrap_or(key);
+
+        self.buckets
+            .entry(format!("{}:{}", hash, route.major()))
+            .or_insert_with(|| Arc::new(Mutex::new(Bucket::default())))
+            .value()
+            .clone()
+    }
+
+    /// Wait until a request on this route is allowed
+    pub async fn acquire(&self, route: &Route) {
+        // Global 429 deadline
+        let global_until = *self.global_until.lock().await;
+        if let Some(until) = global_until {
+            tokio::time::sleep_until(until).await;
+        }
+
+        self.global.acquire().await;
+
+        let bucket = self.bucket(route);
+        let mut bucket = bucket.lock().await;
+
+        if bucket.remaining == 0 {
+            if let Some(reset_at) = bucket.reset_at.take() {
+                // Holding the lock while sleeping keeps other callers queued behind us; the
+                // lock is per bucket and major parameter, so other channels and guilds go on
+                tokio::time::sleep_until(reset_at).await;
+            }
+            bucket.remaining = bucket.limit.max(1);
+        }
+
+        bucket.remaining -= 1;
+    }
+
+    /// Update bucket state from response headers
+    pub async fn update(&self, route: &Route, headers: &HeaderMap) {
+        if let Some(hash) = header_str(headers, "x-ratelimit-bucket") {
+            self.routes.insert(route.key(), hash.to_string());
+        }
+
+        let Some(limit) = header_str(headers, "x-ratelimit-limit").and_then(|v| v.parse().ok())
+        else {
+            return;
+        };
+
+        let remaining = header_str(headers, "x-ratelimit-remaining")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+
+        let reset_after = header_f64(headers, "x-ratelimit-reset-after").unwrap_or(0.0);
+
+        let bucket = self.bucket(route);
+        let mut bucket = bucket.lock().await;
+        bucket.limit = limit;
+        bucket.remaining = remaining;
+        bucket.reset_at = Some(Instant::now() + Duration::from_secs_f64(reset_after.max(0.0)));
+    }
+
+    /// Send a request through the limiter, retrying on HTTP 429.
+    ///
+    /// `build` is called for every attempt because multipart bodies can't be cloned.
+    pub async fn send<F>(&self, route: &Route, build: F) -> Result<Response, BotError>
+    where
+        F: Fn() -> Result<RequestBuilder, BotError>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            self.acquire(route).await;
+            let response = build()?.send().await?;
+
+            self.update(route, response.headers()).await;
+
+            // The limiter works from memory, Mongo only keeps a copy for inspection
+            if response.headers().contains_key("x-ratelimit-limit") {
+                let (key, headers) = (route.key(), response.headers().clone());
+                tokio::spawn(async move {
+                    let _ = RateLimit::update_from_headers(&*state::db().await, key, &headers).await;
+                });
+            }
+
+            if response.status() != StatusCode::TOO_MANY_REQUESTS || attempt >= MAX_ATTEMPTS {
+                return Ok(response);
+            }
+
+            let headers = response.headers().clone();
+            let error_text = response.text().await.unwrap_or_default();
+            let discord_err = serde_json::from_str::<DiscordErrorResponse>(&error_text).ok();
+
+            let retry_after = discord_err
+                .as_ref()
+                .and_then(|e| e.retry_after)
+                .or_else(|| header_f64(&headers, "retry-after"))
+                .unwrap_or(1.0)
+                .max(0.0);
+
+            let global = discord_err.as_ref().and_then(|e| e.global).unwrap_or(false)
+                || header_str(&headers, "x-ratelimit-global") == Some("true");
+
+            eprintln!(
+                "[RATE LIMIT] {} hit 429{}, retrying in {:.3}s (attempt {}/{})",
+                route.key(),
+                if global { " [GLOBAL]" } else { "" },
+                retry_after,
+                attempt,
+                MAX_ATTEMPTS
+            );
+
+            let until = Instant::now() + Duration::from_secs_f64(retry_after);
+
+            if global {
+                *self.global_until.lock().await = Some(until);
+            } else {
+                let bucket = self.bucket(route);
+                let mut bucket = bucket.lock().await;
+                bucket.remaining = 0;
+                bucket.reset_at = Some(until);
+            }
+        }
+    }
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn header_f64(headers: &HeaderMap, name: &str) -> Option<f64> {
+    header_str(headers, name).and_then(|v| v.parse::<f64>().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_keys_template_ids_and_keep_the_major_parameter() {
+        let route = Route::new(Method::PATCH, "/channels/123/messages/456");
+        assert_eq!(route.key(), "PATCH /channels/{channel_id}/messages/{id}");
+        assert_eq!(route.major(), "123");
+
+        let route = Route::new(Method::GET, "/guilds/7/members/8?limit=5");
+        assert_eq!(route.key(), "GET /guilds/{guild_id}/members/{id}");
+        assert_eq!(route.major(), "7");
+
+        let route = Route::new(Method::GET, "/gateway/bot");
+        assert_eq!(route.key(), "GET /gateway/bot");
+        assert_eq!(route.major(), "");
+    }
+
+    #[test]
+    fn webhook_tokens_are_part_of_the_major_parameter() {
+        let route = Route::new(Method::PATCH, "/webhooks/42/tok3n/messages/@original");
+        assert_eq!(route.key(), "PATCH /webhooks/{webhook_id}/{token}/messages/@original");
+        assert_eq!(route.major(), "42/tok3n");
+    }
+
+    #[test]
+    fn interaction_tokens_are_not_part_of_the_major_parameter() {
+        let route = Route::new(Method::POST, "/interactions/99/tok3n/callback");
+        assert_eq!(route.key(), "POST /interactions/{id}/{token}/callback");
+        assert_eq!(route.major(), "");
+    }
+
+    #[test]
+    fn buckets_are_scoped_to_the_major_parameter() {
+        let limiter = RateLimiter::new(50.0);
+        let channel_1 = Route::new(Method::POST, "/channels/1/messages");
+        let channel_2 = Route::new(Method::POST, "/channels/2/messages");
+
+        assert!(Arc::ptr_eq(&limiter.bucket(&channel_1), &limiter.bucket(&channel_1)));
+        assert!(!Arc::ptr_eq(&limiter.bucket(&channel_1), &limiter.bucket(&channel_2)));
+    }
+
+    #[test]
+    fn routes_with_the_same_bucket_hash_share_state() {
+        let limiter = RateLimiter::new(50.0);
+        let create = Route::new(Method::POST, "/channels/1/messages");
+        let edit = Route::new(Method::PATCH, "/channels/1/messages/5");
+        let elsewhere = Route::new(Method::PATCH, "/channels/2/messages/5");
+        for route in [&create, &edit] {
+            limiter.routes.insert(route.key(), "abcd".to_string());
+        }
+
+        assert!(Arc::ptr_eq(&limiter.bucket(&create), &limiter.bucket(&edit)));
+        assert!(!Arc::ptr_eq(&limiter.bucket(&edit), &limiter.bucket(&elsewhere)));
+    }
+}
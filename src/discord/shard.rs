@@ -0,0 +1,173 @@
+use crate::db::session_events::SessionEvent;
+use crate::db::session_limits::SessionLimit;
+use crate::discord::api;
+use crate::discord::discord::GatewayBotInfo;
+use crate::discord::gateway::{Disconnect, run_gateway};
+use crate::error::BotError;
+use crate::state;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, Instant};
+
+/// Discord allows one IDENTIFY per rate limit bucket every 5 seconds
+const IDENTIFY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Identity of a gateway connection and the IDENTIFY queue it shares with the other shards
+#[derive(Clone)]
+pub struct Shard {
+    pub id: u32,
+    pub total: u32,
+    pub compress: bool,
+    pub identify_queue: Arc<IdentifyQueue>,
+}
+
+/// IDENTIFY rate limit buckets: shard `id` belongs to bucket `id % max_concurrency`
+pub struct IdentifyQueue {
+    buckets: Vec<Mutex<Option<Instant>>>,
+}
+
+impl IdentifyQueue {
+    pub fn new(max_concurrency: u32) -> Self {
+        Self {
+            buckets: (0..max_concurrency.max(1)).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    /// Wait for this shard's bucket to allow another IDENTIFY
+    pub async fn wait(&self, shard_id: u32) {
+        let bucket = &self.buckets[shard_id as usize % self.buckets.len()];
+        let mut last_identify = bucket.lock().await;
+
+        if let Some(last) = *last_identify {
+            tokio::time::sleep_until(last + IDENTIFY_INTERVAL).await;
+        }
+
+        *last_identify = Some(Instant::now());
+    }
+}
+
+/// Spawn one gateway connection per recommended shard and keep them running
+pub async fn run_shards(info: GatewayBotInfo, compress: bool) -> Result<(), BotError> {
+    let total = info.shards.max(1) as u32;
+    let max_concurrency = info.session_start_limit.max_concurrency.max(1) as u32;
+    let identify_queue = Arc::new(IdentifyQueue::new(max_concurrency));
+
+    println!(
+        "[INFO] Starting {} shard(s) with max_concurrency {}",
+        total, max_concurrency
+    );
+
+    // Shards beyond the new count are gone for good, as are their sessions
+    state::retain_shards(total).await;
+
+    let mut shards = JoinSet::new();
+
+    for id in 0..total {
+        // Sessions identified with a different shard count can't be resumed
+        if state::get_shard_count(id).await.is_some_and(|count| count != total) {
+            state::clear_session(id).await;
+        }
+
+        let shard = Shard {
+            id,
+            total,
+            compress,
+            identify_queue: identify_queue.clone(),
+        };
+        shards.spawn(run_shard(shard, info.url.clone()));
+    }
+
+    while let Some(result) = shards.join_next().await {
+        result?;
+    }
+
+    Ok(())
+}
+
+/// Reconnect loop of a single shard
+async fn run_shard(shard: Shard, gateway_url: String) {
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        let session_id = state::get_session_id(shard.id).await;
+
+        // Resumes must reconnect to the URL Discord gave us in READY
+        let url = match (&session_id, state::get_resume_gateway_url(shard.id).await) {
+            (Some(_), Some(resume_url)) => api::with_gateway_params(&resume_url, shard.compress),
+            _ => api::with_gateway_params(&gateway_url, shard.compress),
+        };
+
+        let result = async {
+            // IDENTIFY waits happen before connecting, Discord expects heartbeats as soon as HELLO arrives
+            if session_id.is_none() {
+                wait_for_session_budget().await?;
+                shard.identify_queue.wait(shard.id).await;
+            }
+
+            run_gateway(url, &shard).await
+        };
+
+        match result.await {
+            Ok(Disconnect::Reconnect) => {
+                attempt = 0;
+            }
+            Ok(Disconnect::SessionInvalidated) => {
+                attempt = 0;
+
+                // Discord asks for a random 1-5 second wait before identifying again
+                let delay = rand::random_range(1000..=5000);
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+            }
+            Err(e) => {
+                e.print_tree();
+
+                let wait_time = match attempt {
+                    1..=2 => 30,
+                    3..=4 => 60,
+                    5..=6 => 120,
+                    _ => 300,
+                };
+
+                eprintln!(
+                    "[RETRY] Shard {}/{} reconnecting in {} seconds (attempt #{})",
+                    shard.id, shard.total, wait_time, attempt
+                );
+                tokio::time::sleep(Duration::from_secs(wait_time)).await;
+            }
+        }
+    }
+}
+
+/// Sleep until the daily session start limit resets if the stored budget is nearly spent
+async fn wait_for_session_budget() -> Result<(), BotError> {
+    loop {
+        let Some(limit) = SessionLimit::get(&*state::db().await).await? else {
+            return Ok(());
+        };
+
+        if limit.can_start_session() {
+            return Ok(());
+        }
+
+        let wait_seconds = limit.retry_after_seconds();
+        eprintln!(
+            "[GATEWAY] Session start budget nearly exhausted ({}/{} left), waiting {:.0} seconds",
+            limit.remaining, limit.total, wait_seconds
+        );
+        let _ = SessionEvent::log_session_limit_wait(
+            &*state::db().await,
+            limit.remaining,
+            wait_seconds,
+        )
+        .await;
+
+        // Never spin on /gateway/bot if the stored reset time is already behind us
+        tokio::time::sleep(Duration::from_secs_f64(wait_seconds.max(5.0))).await;
+
+        // Fetch the fresh budget from /gateway/bot
+        api::get_gateway_bot_info().await?;
+    }
+}
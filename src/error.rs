@@ -50,10 +50,7 @@ impl BotError {
     #[inline]
     #[allow(dead_code)]
     pub fn push_str(mut self, message: String) -> Self {
-        self.causes.push(Cause::Std(Arc::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            message,
-        ))));
+        self.causes.push(Cause::Std(Arc::new(std::io::Error::other(message))));
         self
     }
 
@@ -65,11 +62,17 @@ impl BotError {
 
     /// Print error with ASCII tree of causes
     pub fn print_tree(&self) {
-        eprintln!("[ERROR] {}:{} - {}", self.file, self.line, self.key);
-        self.print_causes("", true);
+        eprint!("[ERROR] {}", self.tree());
     }
 
-    fn print_causes(&self, prefix: &str, _is_last: bool) {
+    /// ASCII tree of causes, e.g. to store next to a failed job
+    pub fn tree(&self) -> String {
+        let mut out = format!("{}:{} - {}\n", self.file, self.line, self.key);
+        self.write_causes(&mut out, "");
+        out
+    }
+
+    fn write_causes(&self, out: &mut String, prefix: &str) {
         for (i, cause) in self.causes.iter().enumerate() {
             let is_last_cause = i == self.causes.len() - 1;
             let branch = if is_last_cause {
@@ -81,25 +84,31 @@ impl BotError {
 
             match cause {
                 Cause::Any(e) => {
-                    eprintln!("{}{}[{}:{}] {}", prefix, branch, e.file, e.line, e.key);
-                    e.print_causes(&format!("{}{}", prefix, extension), is_last_cause);
+                    out.push_str(&format!("{}{}[{}:{}] {}\n", prefix, branch, e.file, e.line, e.key));
+                    e.write_causes(out, &format!("{}{}", prefix, extension));
                 }
                 Cause::Std(e) => {
-                    eprintln!("{}{}{}", prefix, branch, e);
+                    out.push_str(&format!("{}{}{}\n", prefix, branch, e));
 
                     // Print nested sources
                     let mut source = e.source();
                     let mut depth = 0;
                     while let Some(err) = source {
                         let sub_branch = "    ↳ ";
-                        eprintln!(
-                            "{}{}{}{}",
+                        out.push_str(&format!(
+                            "{}{}{}{}\n",
                             prefix,
                             extension,
                             "  ".repeat(depth),
                             sub_branch
-                        );
-                        eprintln!("{}{}{}  {}", prefix, extension, "  ".repeat(depth + 1), err);
+                        ));
+                        out.push_str(&format!(
+                            "{}{}{}  {}\n",
+                            prefix,
+                            extension,
+                            "  ".repeat(depth + 1),
+                            err
+                        ));
                         source = err.source();
                         depth += 1;
                     }
@@ -122,23 +131,26 @@ impl fmt::Display for BotError {
 impl From<String> for BotError {
     #[track_caller]
     fn from(s: String) -> Self {
-        BotError::new("string_error").push_std(std::io::Error::new(std::io::ErrorKind::Other, s))
+        BotError::new("string_error").push_std(std::io::Error::other(s))
     }
 }
 
 impl From<&str> for BotError {
     #[track_caller]
     fn from(s: &str) -> Self {
-        BotError::new("str_error").push_std(std::io::Error::new(std::io::ErrorKind::Other, s))
+        BotError::new("str_error").push_std(std::io::Error::other(s))
     }
 }
 
 impl std::error::Error for BotError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        self.causes.iter().find_map(|c| match c {
-            Cause::Any(e) => Some(e as &dyn std::error::Error),
-            Cause::Std(e) => Some(e.as_ref()),
-        })
+        self.causes
+            .iter()
+            .map(|c| match c {
+                Cause::Any(e) => e as &dyn std::error::Error,
+                Cause::Std(e) => e.as_ref(),
+            })
+            .next()
     }
 }
 
@@ -233,3 +245,10 @@ impl From<bson::error::Error> for BotError {
         BotError::new("bson").push_std(e)
     }
 }
+
+impl From<flate2::DecompressError> for BotError {
+    #[track_caller]
+    fn from(e: flate2::DecompressError) -> Self {
+        BotError::new("zlib").push_std(e)
+    }
+}
@@ -3,10 +3,12 @@ mod commands;
 mod db;
 mod discord;
 mod error;
+mod role;
 mod state;
 mod workers;
 
 use crate::error::BotError;
+use crate::role::Role;
 use std::env;
 use std::path::Path;
 use tokio::time::Duration;
@@ -141,45 +143,51 @@ async fn download_models_and_runtime() -> Result<(), BotError> {
     Ok(())
 }
 
-async fn register_commands() -> Result<(), BotError> {
+/// Register slash commands, `force` overwrites them even when Discord already has the same set
+async fn register_commands(force: bool) -> Result<(), BotError> {
     println!("[INFO] Starting slash commands registration...");
 
-    let token = state::token().await;
-    let client = state::client().await;
-
     println!("[INFO] Getting application ID...");
-    let app_id = discord::api::get_application_id(&client, &token).await?;
+    let app_id = discord::api::get_application_id().await?;
     println!("[INFO] Application ID: {}", app_id);
 
     // Save application ID to state for invite URL generation
     state::set_application_id(app_id.clone()).await;
 
-    println!("[INFO] Registering slash commands...");
-    discord::api::register_slash_commands(&client, &token, &app_id).await?;
+    // Optional guild for testing, its commands update instantly instead of propagating globally
+    let dev_guild_id = option_env!("DISCORD_DEV_GUILD_ID")
+        .map(String::from)
+        .or_else(|| env::var("DISCORD_DEV_GUILD_ID").ok())
+        .filter(|id| !id.is_empty());
+
+    match &dev_guild_id {
+        Some(guild_id) => println!("[INFO] Registering slash commands in dev guild {}...", guild_id),
+        None => println!("[INFO] Registering global slash commands..."),
+    }
+    discord::api::register_slash_commands(&app_id, dev_guild_id.as_deref(), force).await?;
 
-    println!(
-        "[INFO] Commands registered successfully! Waiting 2 seconds for Discord to process..."
-    );
-    tokio::time::sleep(Duration::from_secs(2)).await;
     println!("[INFO] Commands registration completed!");
     Ok(())
 }
 
 async fn run_bot() -> Result<(), BotError> {
-    // Fetch and store bot info with session limits
-    let token = state::token().await;
-    let client = state::client().await;
-    let _ = discord::api::get_gateway_bot_info(&client, &token).await;
+    // Fetch and store bot info with session limits and recommended shard count
+    let bot_info = discord::api::get_gateway_bot_info().await?;
 
-    // Commands are only registered manually via SIGUSR1 signal
-    // No automatic registration on startup to avoid rate limiting
+    // Opt-in zlib-stream transport compression for the gateway
+    let compress = option_env!("DISCORD_GATEWAY_COMPRESS")
+        .map(String::from)
+        .or_else(|| env::var("DISCORD_GATEWAY_COMPRESS").ok())
+        .is_some_and(|value| value == "1" || value.eq_ignore_ascii_case("true"));
 
-    let gateway_url = discord::api::get_gateway_url(&client, &token).await?;
-    discord::gateway::run_gateway(gateway_url).await
+    discord::shard::run_shards(bot_info, compress).await
 }
 
 #[tokio::main]
 async fn main() -> Result<(), BotError> {
+    // Bad arguments fail before anything connects
+    let role = Role::from_args(env::args().skip(1))?;
+
     let token = option_env!("DISCORD_BOT_TOKEN")
         .map(String::from)
         .or_else(|| env::var("DISCORD_BOT_TOKEN").ok())
@@ -195,24 +203,58 @@ async fn main() -> Result<(), BotError> {
         .or_else(|| env::var("MONGO_DB").ok())
         .expect("MONGO_DB not set at compile time or runtime");
 
-    state::init_bot_state(token, &mongo_url, &mongo_db).await?;
+    // Optional override, e.g. to point the bot at a local mock server
+    let api_base_url = option_env!("DISCORD_API_URL")
+        .map(String::from)
+        .or_else(|| env::var("DISCORD_API_URL").ok())
+        .unwrap_or_else(|| discord::http::DEFAULT_BASE_URL.to_string());
+
+    state::init_bot_state(token, &mongo_url, &mongo_db, api_base_url).await?;
+
+    let pools = role.pools();
+    workers::processor::enable_pools(pools.clone());
+    println!("[INFO] Running as {:?}", role);
+
+    if !pools.is_empty() {
+        // Requeue jobs a crashed or restarted worker left in processing, then keep watching for expired leases
+        workers::lease::spawn_sweeper();
+
+        // A separate gateway enqueues the jobs, so worker processes watch by default
+        let job_watch: workers::watch::WatchMode = match option_env!("JOB_WATCH")
+            .map(String::from)
+            .or_else(|| env::var("JOB_WATCH").ok())
+        {
+            Some(mode) => mode.parse()?,
+            None if role.runs_gateway() => workers::watch::WatchMode::Off,
+            None => workers::watch::WatchMode::Auto,
+        };
+        workers::watch::spawn_all(job_watch);
+    }
 
-    // Setup SIGUSR1 signal handler for command reregistration
-    tokio::spawn(async {
-        use tokio::signal::unix::{SignalKind, signal};
-        let mut stream =
-            signal(SignalKind::user_defined1()).expect("Failed to setup SIGUSR1 handler");
-        loop {
-            stream.recv().await;
-            eprintln!("[SIGNAL] Received SIGUSR1 - reregistering commands...");
-            if let Err(e) = register_commands().await {
-                eprintln!("[ERROR] Failed to reregister commands:");
-                e.print_tree();
-            } else {
-                eprintln!("[SIGNAL] Commands reregistered successfully");
-            }
+    if role.runs_gateway() {
+        // Safe on every boot, nothing is sent when Discord already has the same commands
+        if let Err(e) = register_commands(false).await {
+            eprintln!("[ERROR] Failed to register commands:");
+            e.print_tree();
         }
-    });
+
+        // Setup SIGUSR1 signal handler for command reregistration
+        tokio::spawn(async {
+            use tokio::signal::unix::{SignalKind, signal};
+            let mut stream =
+                signal(SignalKind::user_defined1()).expect("Failed to setup SIGUSR1 handler");
+            loop {
+                stream.recv().await;
+                eprintln!("[SIGNAL] Received SIGUSR1 - reregistering commands...");
+                if let Err(e) = register_commands(true).await {
+                    eprintln!("[ERROR] Failed to reregister commands:");
+                    e.print_tree();
+                } else {
+                    eprintln!("[SIGNAL] Commands reregistered successfully");
+                }
+            }
+        });
+    }
 
     // Setup SIGUSR2 signal handler for downloading models
     tokio::spawn(async {
@@ -229,6 +271,11 @@ async fn main() -> Result<(), BotError> {
         }
     });
 
+    // Headless workers only consume the queues, the spawned tasks do all the work
+    if !role.runs_gateway() {
+        std::future::pending::<()>().await;
+    }
+
     let mut attempt = 0;
 
     // Infinite retry loop
@@ -0,0 +1,101 @@
+//! Which parts of the bot this process runs, picked on the command line
+//!
+//! ```text
+//! warraft-discord                          # gateway and every worker pool
+//! warraft-discord all
+//! warraft-discord gateway                  # only enqueues jobs
+//! warraft-discord worker --pools blp,icon  # only consumes the queues
+//! ```
+
+use crate::error::BotError;
+use crate::workers::blp::job::JobBlp;
+use crate::workers::icon::job::JobIcon;
+use crate::workers::job::Job;
+use crate::workers::rembg::job::JobRembg;
+
+/// Every worker pool the bot has
+pub const POOLS: [&str; 3] = [
+    <JobBlp as Job>::POOL,
+    <JobIcon as Job>::POOL,
+    <JobRembg as Job>::POOL,
+];
+
+const USAGE: &str = "usage: [all | gateway | worker [--pools blp,icon,rembg]]";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Role {
+    /// Gateway and every worker pool in one process
+    All,
+    /// Gateway connection only, jobs stay in the queue for worker processes
+    Gateway,
+    /// Worker pools only, talking to Mongo and REST without a gateway connection
+    Worker { pools: Vec<String> },
+}
+
+impl Role {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Role, BotError> {
+        let role = match args.next().as_deref() {
+            None | Some("all") => Role::All,
+            Some("gateway") => Role::Gateway,
+            Some("worker") => {
+                let pools = match args.next() {
+                    None => POOLS.iter().map(|pool| pool.to_string()).collect(),
+                    Some(flag) if flag == "--pools" => parse_pools(args.next().unwrap_or_default())?,
+                    Some(flag) => match flag.strip_prefix("--pools=") {
+                        Some(list) => parse_pools(list.to_string())?,
+                        None => return Err(usage_error(&flag)),
+                    },
+                };
+                Role::Worker { pools }
+            }
+            Some(other) => return Err(usage_error(other)),
+        };
+
+        if let Some(extra) = args.next() {
+            return Err(usage_error(&extra));
+        }
+
+        Ok(role)
+    }
+
+    pub fn runs_gateway(&self) -> bool {
+        !matches!(self, Role::Worker { .. })
+    }
+
+    /// Worker pools this process runs, empty for a pure gateway
+    pub fn pools(&self) -> Vec<String> {
+        match self {
+            Role::All => POOLS.iter().map(|pool| pool.to_string()).collect(),
+            Role::Gateway => Vec::new(),
+            Role::Worker { pools } => pools.clone(),
+        }
+    }
+}
+
+fn parse_pools(list: String) -> Result<Vec<String>, BotError> {
+    let mut pools = Vec::new();
+
+    for pool in list.split(',').map(str::trim).filter(|pool| !pool.is_empty()) {
+        if !POOLS.contains(&pool) {
+            return Err(BotError::new("unknown_pool").push_str(format!(
+                "Unknown pool '{}', available: {}",
+                pool,
+                POOLS.join(", ")
+            )));
+        }
+        if !pools.iter().any(|existing| existing == pool) {
+            pools.push(pool.to_string());
+        }
+    }
+
+    if pools.is_empty() {
+        return Err(BotError::new("no_pools").push_str(format!("No pools given, {}", USAGE)));
+    }
+
+    Ok(pools)
+}
+
+fn usage_error(argument: &str) -> BotError {
+    BotError::new("invalid_arguments")
+        .push_str(format!("Unexpected argument '{}', {}", argument, USAGE))
+}
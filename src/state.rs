@@ -1,69 +1,27 @@
 use reqwest::Client;
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
 use tokio::sync::{Mutex, OnceCell};
-use tokio::time::Instant;
 
-use crate::db::{mongo::mongo_pool, state::DiscordState};
+use crate::db::{
+    mongo::mongo_pool,
+    state::{DiscordState, ShardSession},
+};
+use crate::discord::http::DiscordHttp;
+use crate::discord::rate_limiter::RateLimiter;
 use crate::error::BotError;
 
 static BOT_STATE: OnceCell<Arc<BotStateInner>> = OnceCell::const_new();
 
-/// Rate limiter using token bucket algorithm
-pub(crate) struct RateLimiter {
-    tokens: Mutex<f64>,
-    max_tokens: f64,
-    refill_rate: f64, // tokens per second
-    last_refill: Mutex<Instant>,
-}
-
-impl RateLimiter {
-    fn new(requests_per_second: f64) -> Self {
-        Self {
-            tokens: Mutex::new(requests_per_second),
-            max_tokens: requests_per_second,
-            refill_rate: requests_per_second,
-            last_refill: Mutex::new(Instant::now()),
-        }
-    }
-
-    pub async fn acquire(&self) {
-        loop {
-            // Refill tokens based on elapsed time
-            let now = Instant::now();
-            let mut last_refill = self.last_refill.lock().await;
-            let elapsed = now.duration_since(*last_refill).as_secs_f64();
-            
-            let mut tokens = self.tokens.lock().await;
-            let new_tokens = (*tokens + elapsed * self.refill_rate).min(self.max_tokens);
-            *tokens = new_tokens;
-            *last_refill = now;
-
-            // Try to consume one token
-            if *tokens >= 1.0 {
-                *tokens -= 1.0;
-                return;
-            }
-
-            // Not enough tokens, wait for refill
-            drop(tokens);
-            drop(last_refill);
-            tokio::time::sleep(Duration::from_millis(50)).await;
-        }
-    }
-}
-
 pub(crate) struct BotStateInner {
     token: String,
     client: Client,
-    sequence: Mutex<Option<u64>>,
-    session_id: Mutex<Option<String>>,
+    shards: Mutex<HashMap<u32, ShardSession>>,
     db: Arc<mongodb::Database>,
     bot_user_id: Mutex<Option<String>>,
     application_id: Mutex<Option<String>>,
-    // Rate limiter: Discord allows ~50 requests per second globally
-    // We use 45/sec to have safety margin
-    rate_limiter: Arc<RateLimiter>,
+    // REST client with per-bucket rate limiter; global throughput is capped below Discord's ~50 req/sec
+    http: Arc<DiscordHttp>,
 }
 
 pub(crate) async fn bot_state() -> Arc<BotStateInner> {
@@ -73,7 +31,12 @@ pub(crate) async fn bot_state() -> Arc<BotStateInner> {
         .clone()
 }
 
-pub async fn init_bot_state(token: String, mongo_url: &str, mongo_db: &str) -> Result<(), BotError> {
+pub async fn init_bot_state(
+    token: String,
+    mongo_url: &str,
+    mongo_db: &str,
+    api_base_url: String,
+) -> Result<(), BotError> {
     let db = mongo_pool(mongo_url, mongo_db).await;
     let saved_state = DiscordState::load(&db).await?;
     
@@ -81,66 +44,119 @@ pub async fn init_bot_state(token: String, mongo_url: &str, mongo_db: &str) -> R
     let rate_limit = saved_state.rate_limit.unwrap_or(40.0);
     
     BOT_STATE.get_or_init(|| async {
+        let client = Client::new();
+        let http = DiscordHttp::new(
+            token.clone(),
+            client.clone(),
+            Arc::new(RateLimiter::new(rate_limit)),
+            api_base_url,
+        );
+
         Arc::new(BotStateInner {
             token,
-            client: Client::new(),
-            sequence: Mutex::new(saved_state.sequence),
-            session_id: Mutex::new(saved_state.session_id),
+            client,
+            shards: Mutex::new(
+                saved_state
+                    .shards
+                    .into_iter()
+                    .map(|shard| (shard.shard_id, shard))
+                    .collect(),
+            ),
             db,
             bot_user_id: Mutex::new(saved_state.bot_user_id),
             application_id: Mutex::new(None),
-            rate_limiter: Arc::new(RateLimiter::new(rate_limit)),
+            http: Arc::new(http),
         })
     }).await;
     
     Ok(())
 }
 
-pub async fn update_sequence(seq: Option<u64>) {
+pub async fn update_sequence(shard_id: u32, seq: Option<u64>) {
     if let Some(s) = seq {
         let state = bot_state().await;
-        *state.sequence.lock().await = Some(s);
+        state
+            .shards
+            .lock()
+            .await
+            .entry(shard_id)
+            .or_insert_with(|| ShardSession {
+                shard_id,
+                ..Default::default()
+            })
+            .sequence = Some(s);
         let _ = save_state().await;
     }
 }
 
-pub async fn get_sequence() -> Option<u64> {
+async fn shard_session(shard_id: u32) -> Option<ShardSession> {
     let state = bot_state().await;
-    *state.sequence.lock().await
+    state.shards.lock().await.get(&shard_id).cloned()
+}
+
+pub async fn get_sequence(shard_id: u32) -> Option<u64> {
+    shard_session(shard_id).await.and_then(|s| s.sequence)
+}
+
+pub async fn get_session_id(shard_id: u32) -> Option<String> {
+    shard_session(shard_id).await.and_then(|s| s.session_id)
 }
 
-pub async fn get_session_id() -> Option<String> {
+pub async fn get_resume_gateway_url(shard_id: u32) -> Option<String> {
+    shard_session(shard_id).await.and_then(|s| s.resume_gateway_url)
+}
+
+/// Shard count the stored session was identified with
+pub async fn get_shard_count(shard_id: u32) -> Option<u32> {
+    shard_session(shard_id).await.map(|s| s.shard_count)
+}
+
+pub async fn set_session(
+    shard_id: u32,
+    shard_count: u32,
+    id: String,
+    resume_gateway_url: Option<String>,
+) {
     let state = bot_state().await;
-    state.session_id.lock().await.clone()
+    {
+        let mut shards = state.shards.lock().await;
+        let shard = shards.entry(shard_id).or_insert_with(|| ShardSession {
+            shard_id,
+            ..Default::default()
+        });
+        shard.shard_count = shard_count;
+        shard.session_id = Some(id);
+        shard.resume_gateway_url = resume_gateway_url;
+    }
+    let _ = save_state().await;
 }
 
-pub async fn set_session_id(id: String) {
+pub async fn clear_session(shard_id: u32) {
     let state = bot_state().await;
-    *state.session_id.lock().await = Some(id.clone());
+    state.shards.lock().await.remove(&shard_id);
     let _ = save_state().await;
 }
 
-pub async fn clear_session() {
+/// Forget sessions of shards outside `0..total`, e.g. after Discord lowered the shard count
+pub async fn retain_shards(total: u32) {
     let state = bot_state().await;
-    *state.session_id.lock().await = None;
-    *state.sequence.lock().await = None;
+    state.shards.lock().await.retain(|&shard_id, _| shard_id < total);
     let _ = save_state().await;
 }
 
 async fn save_state() -> Result<(), BotError> {
     let state = bot_state().await;
-    let session_id = state.session_id.lock().await.clone();
-    let sequence = *state.sequence.lock().await;
+    let mut shards: Vec<ShardSession> = state.shards.lock().await.values().cloned().collect();
+    shards.sort_by_key(|s| s.shard_id);
     let bot_user_id = state.bot_user_id.lock().await.clone();
-    
+
     let discord_state = DiscordState {
         id: "bot_state".to_string(),
-        session_id,
-        sequence,
+        shards,
         bot_user_id,
         rate_limit: None, // Don't override DB value when saving session state
     };
-    
+
     discord_state.save(&state.db).await
 }
 
@@ -198,8 +214,8 @@ pub async fn get_invite_url() -> String {
     )
 }
 
-/// Get the rate limiter for Discord API requests
-pub async fn rate_limiter() -> Arc<RateLimiter> {
+/// Get the Discord REST client
+pub async fn http() -> Arc<DiscordHttp> {
     let state = bot_state().await;
-    state.rate_limiter.clone()
+    state.http.clone()
 }
@@ -1,3 +1,4 @@
+use crate::discord::discord::InteractionTarget;
 use crate::discord::message::handle::CommandArgs;
 use crate::discord::message::message::Message;
 use crate::error::BotError;
@@ -5,21 +6,30 @@ use crate::state;
 use crate::workers::blp::job::{ConversionTarget, JobBlp};
 use crate::workers::blp::processor::BlpProcessor;
 use crate::workers::processor::notify_workers;
+use crate::workers::quota;
 use mongodb::Collection;
 
 pub async fn handle(
     message: Message,
     target: ConversionTarget,
     args: CommandArgs,
+    interaction: Option<InteractionTarget>,
 ) -> Result<(), BotError> {
+    if !quota::admit(&message, interaction.as_ref()).await? {
+        return Ok(());
+    }
+
     let db = state::db().await;
     let collection: Collection<JobBlp> = db.collection(JobBlp::COLLECTION);
 
     collection
         .insert_one(JobBlp {
             message,
+            interaction,
             target,
             quality: args.quality,
+            mip_count: args.mip_count,
+            alpha_depth: args.alpha_depth,
             zip: args.zip,
             created: chrono::Utc::now(),
             ..Default::default()
@@ -1,3 +1,4 @@
+use crate::discord::discord::InteractionTarget;
 use crate::discord::message::message::Message;
 use crate::error::BotError;
 use crate::workers::queue::QueueStatus;
@@ -21,10 +22,22 @@ pub struct JobBlp {
 
     pub reply: Option<Message>,
 
+    /// Slash command jobs reply through the interaction webhook instead of the channel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interaction: Option<InteractionTarget>,
+
     pub target: ConversionTarget,
 
     pub quality: u8,
 
+    /// Number of mip levels to encode; all of them when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mip_count: Option<u8>,
+
+    /// Alpha bits of the texture, 0 encodes it fully opaque; the image's alpha when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alpha_depth: Option<u8>,
+
     pub zip: bool,
 
     pub status: QueueStatus,
@@ -36,11 +49,26 @@ pub struct JobBlp {
     #[serde_as(as = "Option<datetime::FromChrono04DateTime>")]
     pub completed: Option<DateTime<Utc>>,
 
+    /// Error tree of the last failed attempt
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
 
     #[serde(default)]
     pub retry: u32,
+
+    /// Backoff after a failed attempt, the job isn't claimed again before this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<datetime::FromChrono04DateTime>")]
+    pub not_before: Option<DateTime<Utc>>,
+
+    /// Processing lease end; the sweeper returns the job to the queue once it passes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<datetime::FromChrono04DateTime>")]
+    pub locked_until: Option<DateTime<Utc>>,
+
+    /// Process holding the lease
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_id: Option<String>,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Display, EnumString)]
@@ -48,13 +76,22 @@ pub struct JobBlp {
 #[strum(serialize_all = "UPPERCASE")]
 pub enum ConversionTarget {
     #[default]
-    BLP, // PNG/JPG → BLP
-    PNG, // BLP → PNG
+    Blp, // PNG/JPG → BLP
+    Png, // BLP → PNG
 }
 
 impl JobBlp {
     pub const COLLECTION: &'static str = "discord_command_blp";
-    pub const MAX_RETRIES: u32 = 3;
+
+    /// Find a job by its hex id, e.g. from a component custom_id
+    pub async fn find_by_id(db: &mongodb::Database, id: &str) -> Result<Option<Self>, BotError> {
+        let Ok(id) = ObjectId::parse_str(id) else {
+            return Ok(None);
+        };
+
+        let collection: Collection<JobBlp> = db.collection(Self::COLLECTION);
+        Ok(collection.find_one(doc! { "_id": id }).await?)
+    }
 
     /// Count pending items
     #[allow(dead_code)]
@@ -1,279 +1,144 @@
-use crate::discord::message::attachment::{AttachmentVecExt, ensure_unique_filenames};
-use crate::discord::message::message::MessageReference;
-use crate::discord::message::send::MessageSend;
+use crate::commands::components::blp_actions;
+use crate::db::guild_config::Language;
+use crate::discord::discord::InteractionTarget;
+use crate::discord::message::attachment::AttachmentMemory;
+use crate::discord::message::component::ActionRow;
+use crate::discord::message::message::Message;
 use crate::error::BotError;
-use crate::state;
 use crate::workers::blp::job::{ConversionTarget, JobBlp};
-use crate::workers::processor::{TaskProcessor, notify_workers};
-use crate::workers::queue::QueueStatus;
+use crate::workers::job::{Converted, Job, JobProcessor};
 use async_trait::async_trait;
 use blp::core::image::ImageBlp;
-use bson::{Bson, doc, serialize_to_bson};
+use chrono::{DateTime, Utc};
 use image::{DynamicImage, ImageFormat};
-use mongodb::Collection;
-use reqwest::Method;
-use std::io::{Cursor, Write};
-use zip::ZipWriter;
-use zip::write::FileOptions;
+use mongodb::bson::oid::ObjectId;
+use std::io::Cursor;
+
+pub type BlpProcessor = JobProcessor<JobBlp>;
 
-pub struct BlpProcessor;
 #[async_trait]
-impl TaskProcessor for BlpProcessor {
+impl Job for JobBlp {
+    const COLLECTION: &'static str = JobBlp::COLLECTION;
     const POOL: &'static str = "blp";
 
-    async fn process_queue_item() -> Result<bool, BotError> {
-        let db = state::db().await;
-        let collection: Collection<JobBlp> = db.collection(JobBlp::COLLECTION);
-
-        let result = collection
-            .find_one_and_update(
-                doc! {
-                    JobBlp::STATUS: QueueStatus::Pending.as_ref(),
-                    JobBlp::RETRY: { "$lt": JobBlp::MAX_RETRIES }
-                },
-                doc! {
-                    "$set": {
-                        JobBlp::STATUS: QueueStatus::Processing.as_ref()
-                    }
-                },
-            )
-            .sort(doc! { JobBlp::CREATED: 1 })
-            .return_document(mongodb::options::ReturnDocument::After)
-            .await?;
-
-        let Some(job) = result else {
-            return Ok(false);
-        };
-
-        let Some(ref reply) = job.reply else {
-            if job.message.attachments.is_empty() {
-                let reply_msg = MessageSend {
-                    content: Some("❌ No attachments found — nothing to convert.".to_string()),
-                    message_reference: Some(MessageReference {
-                        message_id: Some(job.message.id.clone()),
-                        ..Default::default()
-                    }),
-                    attachments: None,
-                }
-                .send(Method::POST, &job.message.channel_id, None)
-                .await?;
-
-                collection
-                    .update_one(
-                        doc! { "_id": &job.id },
-                        doc! {
-                            "$set": {
-                                JobBlp::REPLY: serialize_to_bson(&reply_msg)?,
-                                JobBlp::STATUS: QueueStatus::Completed.as_ref(),
-                                JobBlp::COMPLETED: Bson::DateTime(bson::DateTime::now())
-                            },
-                        },
-                    )
-                    .await?;
-            } else {
-                let reply_msg = MessageSend {
-                    content: Some(format!(
-                        "✅ Added {} image(s) to conversion queue {}\n⏳ Processing...",
-                        job.message.attachments.len(),
-                        match job.target {
-                            ConversionTarget::BLP => format!("to BLP (quality: {})", job.quality),
-                            ConversionTarget::PNG => "to PNG".to_string(),
-                        }
-                    )),
-                    message_reference: Some(MessageReference {
-                        message_id: Some(job.message.id.clone()),
-                        ..Default::default()
-                    }),
-                    attachments: None,
-                }
-                .send(Method::POST, &job.message.channel_id, None)
-                .await?;
-
-                collection
-                    .update_one(
-                        doc! { "_id": &job.id },
-                        doc! {
-                            "$set": {
-                                JobBlp::REPLY: serialize_to_bson(&reply_msg)?,
-                                JobBlp::STATUS: QueueStatus::Pending.as_ref(),
-                            },
-                        },
-                    )
-                    .await?;
-            }
-
-            notify_workers::<BlpProcessor>();
-            return Ok(true);
-        };
-
-        let attachment = ensure_unique_filenames(job.message.attachments)
-            .download_all(4)
-            .await;
-
-        let mut converted_files = Vec::new();
-
-        for attachment_memory in attachment {
-            if let Some(ref error) = attachment_memory.error {
-                let error_filename = format!("{}.error.txt", attachment_memory.filename_stem);
+    fn id(&self) -> Option<ObjectId> {
+        self.id
+    }
+    fn message(&self) -> &Message {
+        &self.message
+    }
+    fn reply(&self) -> Option<&Message> {
+        self.reply.as_ref()
+    }
+    fn interaction(&self) -> Option<&InteractionTarget> {
+        self.interaction.as_ref()
+    }
+    fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+    fn retry(&self) -> u32 {
+        self.retry
+    }
+    fn zip(&self) -> bool {
+        self.zip
+    }
 
-                let error_content = format!(
-                    "Error downloading file: {}\n\nError details:\n{}\n\nTimestamp: {}",
-                    attachment_memory.meta.filename,
-                    error,
-                    chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
-                );
-                converted_files.push((error_filename, error_content.into_bytes()));
-                continue;
-            }
+    fn describe(&self, language: Language) -> String {
+        match self.target {
+            ConversionTarget::Blp => language.pick(
+                format!("to BLP (quality: {})", self.quality),
+                format!("в BLP (качество: {})", self.quality),
+            ),
+            ConversionTarget::Png => language.pick("to PNG", "в PNG").to_string(),
+        }
+    }
 
-            let result: Result<(String, Vec<u8>), BotError> = match job.target {
-                ConversionTarget::BLP => {
-                    let output_filename = format!("{}.blp", attachment_memory.filename_stem);
+    async fn convert(&self, attachment: &AttachmentMemory) -> Result<Converted, BotError> {
+        let (quality, mip_count, alpha_depth) = (self.quality, self.mip_count, self.alpha_depth);
 
-                    let blp_bytes = tokio::task::spawn_blocking({
-                        let image_data = attachment_memory.bytes.to_vec();
-                        move || {
-                            let mut img = ImageBlp::from_buf(&image_data)?;
+        let file = match self.target {
+            ConversionTarget::Blp => {
+                let output_filename = format!("{}.blp", attachment.filename_stem);
 
-                            let mip_visible = vec![true; 16];
-                            img.decode(&image_data, &mip_visible)?;
+                let blp_bytes = tokio::task::spawn_blocking({
+                    let image_data = attachment.bytes.to_vec();
+                    move || {
+                        let mut img = ImageBlp::from_buf(&image_data)?;
 
-                            let ctx = img.encode_blp(job.quality, &mip_visible)?;
+                        let mip_visible = vec![true; 16];
+                        img.decode(&image_data, &mip_visible)?;
 
-                            Ok::<_, blp::error::error::BlpError>(ctx.bytes)
+                        // Without alpha bits every level is encoded fully opaque
+                        if alpha_depth == Some(0) {
+                            let levels = img.mipmaps.iter_mut();
+                            for image in levels.filter_map(|mip| mip.image.as_mut()) {
+                                image.pixels_mut().for_each(|pixel| pixel[3] = u8::MAX);
+                            }
                         }
-                    })
-                    .await??;
-
-                    Ok((output_filename, blp_bytes))
-                }
-                ConversionTarget::PNG => {
-                    let output_filename = format!("{}.png", attachment_memory.filename_stem);
-
-                    let png_bytes = tokio::task::spawn_blocking({
-                        let blp_data = attachment_memory.bytes.to_vec();
-                        move || {
-                            let mut img = ImageBlp::from_buf(&blp_data)?;
-
-                            // Decode only first mip level
-                            img.decode(
-                                &blp_data,
-                                &[
-                                    true, false, false, false, false, false, false, false, false,
-                                    false, false, false, false, false, false, false,
-                                ],
-                            )?;
 
-                            let rgba = img
-                                .mipmaps
-                                .get(0)
-                                .ok_or_else(|| blp::error::error::BlpError::new("no_mipmap"))?
-                                .image
-                                .as_ref()
-                                .ok_or_else(|| blp::error::error::BlpError::new("no_image_data"))?;
+                        // Encode the first `mip_count` levels, all of them by default
+                        let mip_encoded: Vec<bool> = (0..16)
+                            .map(|level| mip_count.is_none_or(|count| level < count as usize))
+                            .collect();
 
-                            let mut png_buffer = Cursor::new(Vec::new());
-                            DynamicImage::ImageRgba8(rgba.clone())
-                                .write_to(&mut png_buffer, ImageFormat::Png)?;
+                        let ctx = img.encode_blp(quality, &mip_encoded)?;
 
-                            Ok::<_, blp::error::error::BlpError>(png_buffer.into_inner())
-                        }
-                    })
-                    .await??;
-
-                    Ok((output_filename, png_bytes))
-                }
-            };
-
-            match result {
-                Ok((filename, bytes)) => {
-                    converted_files.push((filename, bytes));
-                }
-                Err(e) => {
-                    let error_filename = format!("{}.error.txt", attachment_memory.filename_stem);
+                        Ok::<_, blp::error::error::BlpError>(ctx.bytes)
+                    }
+                })
+                .await??;
 
-                    let error_content = format!(
-                        "Error converting file: {}\n\nError details:\n{:?}\n\nTimestamp: {}",
-                        attachment_memory.meta.filename,
-                        e,
-                        chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
-                    );
-                    converted_files.push((error_filename, error_content.into_bytes()));
-                }
+                (output_filename, blp_bytes)
             }
-        }
-
-        // Send response
-        {
-            let conversion_time = format!(
-                "{:.2}s",
-                chrono::Utc::now()
-                    .signed_duration_since(job.created)
-                    .num_milliseconds() as f64
-                    / 1000.0
-            );
-
-            let files_to_send = if job.zip {
-                let mut zip_buffer = Vec::new();
-                {
-                    let cursor = Cursor::new(&mut zip_buffer);
-                    let mut zip = ZipWriter::new(cursor);
-                    let options = FileOptions::<()>::default()
-                        .compression_method(zip::CompressionMethod::Stored);
-
-                    for (filename, data) in &converted_files {
-                        zip.start_file(filename, options)?;
-                        zip.write_all(data)?;
+            ConversionTarget::Png => {
+                let output_filename = format!("{}.png", attachment.filename_stem);
+
+                let png_bytes = tokio::task::spawn_blocking({
+                    let blp_data = attachment.bytes.to_vec();
+                    move || {
+                        let mut img = ImageBlp::from_buf(&blp_data)?;
+
+                        // Decode only first mip level
+                        img.decode(
+                            &blp_data,
+                            &[
+                                true, false, false, false, false, false, false, false, false,
+                                false, false, false, false, false, false, false,
+                            ],
+                        )?;
+
+                        let rgba = img
+                            .mipmaps
+                            .first()
+                            .ok_or_else(|| blp::error::error::BlpError::new("no_mipmap"))?
+                            .image
+                            .as_ref()
+                            .ok_or_else(|| blp::error::error::BlpError::new("no_image_data"))?;
+
+                        let mut png_buffer = Cursor::new(Vec::new());
+                        DynamicImage::ImageRgba8(rgba.clone())
+                            .write_to(&mut png_buffer, ImageFormat::Png)?;
+
+                        Ok::<_, blp::error::error::BlpError>(png_buffer.into_inner())
                     }
+                })
+                .await??;
 
-                    zip.finish()?;
-                }
-                let zip_filename = match job.target {
-                    ConversionTarget::BLP => "converted_images.blp.zip".to_string(),
-                    ConversionTarget::PNG => "converted_images.png.zip".to_string(),
-                };
-                vec![(zip_filename, zip_buffer)]
-            } else {
-                converted_files.clone()
-            };
-
-            let format_desc = match job.target {
-                ConversionTarget::BLP => {
-                    format!("to BLP (quality: {})", job.quality)
-                }
-                ConversionTarget::PNG => "to PNG".to_string(),
-            };
-
-            let _ = MessageSend {
-                content: Some(format!(
-                    "✅ Converted {} image(s) {}{}\n⏱️ Completed in {}",
-                    converted_files.len(),
-                    format_desc,
-                    if job.zip { " (zipped)" } else { "" },
-                    conversion_time
-                )),
-                message_reference: None,
-                attachments: Some(files_to_send),
+                (output_filename, png_bytes)
             }
-            .send(Method::PATCH, &job.message.channel_id, Some(&reply.id))
-            .await?;
-        }
+        };
 
-        let collection: Collection<JobBlp> = db.collection(JobBlp::COLLECTION);
+        Ok(vec![file].into())
+    }
 
-        collection
-            .update_one(
-                doc! { "_id": job.id.unwrap() },
-                doc! {
-                    "$set": {
-                        JobBlp::STATUS: QueueStatus::Completed.as_ref(),
-                        JobBlp::COMPLETED: Bson::DateTime(bson::DateTime::now())
-                    }
-                },
-            )
-            .await?;
+    fn archive_name(&self) -> String {
+        match self.target {
+            ConversionTarget::Blp => "converted_images.blp.zip".to_string(),
+            ConversionTarget::Png => "converted_images.png.zip".to_string(),
+        }
+    }
 
-        notify_workers::<BlpProcessor>();
-        Ok(true)
+    fn components(&self, file_count: usize) -> Option<Vec<ActionRow>> {
+        blp_actions(self.id, &self.target, self.quality, self.zip, file_count)
     }
 }
@@ -1,19 +1,35 @@
+use crate::discord::discord::InteractionTarget;
 use crate::discord::message::handle::CommandArgs;
 use crate::discord::message::message::Message;
 use crate::error::BotError;
 use crate::state;
 use crate::workers::processor::notify_workers;
+use crate::workers::quota;
 use crate::workers::icon::job::JobIcon;
 use crate::workers::icon::processor::IconProcessor;
 use mongodb::Collection;
 
-pub async fn handle(message: Message, _args: &CommandArgs) -> Result<(), BotError> {
+pub async fn handle(
+    message: Message,
+    args: &CommandArgs,
+    interaction: Option<InteractionTarget>,
+) -> Result<(), BotError> {
+    if !quota::admit(&message, interaction.as_ref()).await? {
+        return Ok(());
+    }
+
     let db = state::db().await;
     let collection: Collection<JobIcon> = db.collection(JobIcon::COLLECTION);
 
     collection
         .insert_one(JobIcon {
             message,
+            interaction,
+            variants: args.variants.clone(),
+            crop_x: args.crop_x,
+            crop_y: args.crop_y,
+            output_path: args.output_path.clone(),
+            frame_style: args.frame_style,
             zip: true, // Always create archive
             created: chrono::Utc::now(),
             ..Default::default()
@@ -1,10 +1,13 @@
+use crate::assets::FrameStyle;
+use crate::discord::discord::InteractionTarget;
 use crate::discord::message::message::Message;
 use crate::error::BotError;
 use crate::workers::queue::QueueStatus;
 use bson::serde_helpers::datetime;
 use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
 use mongodb::Collection;
-use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::bson::{Document, doc, oid::ObjectId};
 use proc_macros::define_field_names;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
@@ -20,8 +23,31 @@ pub struct JobIcon {
 
     pub reply: Option<Message>,
 
+    /// Slash command jobs reply through the interaction webhook instead of the channel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interaction: Option<InteractionTarget>,
+
     pub zip: bool,
 
+    /// Border variants to render (BTN, DISBTN, ...); empty renders all of them
+    #[serde(default)]
+    pub variants: Vec<String>,
+
+    /// Top-left corner of the square crop; centered when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crop_x: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crop_y: Option<u32>,
+
+    /// Archive folder for all icons instead of the default ReplaceableTextures paths
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_path: Option<String>,
+
+    /// Frame drawn over every variant, from the server settings
+    #[serde(default)]
+    pub frame_style: FrameStyle,
+
     pub status: QueueStatus,
 
     #[serde_as(as = "datetime::FromChrono04DateTime")]
@@ -31,13 +57,84 @@ pub struct JobIcon {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub completed: Option<DateTime<Utc>>,
 
+    /// Error tree of the last failed attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
     #[serde(default)]
     pub retry: u32,
+
+    /// Backoff after a failed attempt, the job isn't claimed again before this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<datetime::FromChrono04DateTime>")]
+    pub not_before: Option<DateTime<Utc>>,
+
+    /// Processing lease end; the sweeper returns the job to the queue once it passes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<datetime::FromChrono04DateTime>")]
+    pub locked_until: Option<DateTime<Utc>>,
+
+    /// Process holding the lease
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_id: Option<String>,
 }
 
 impl JobIcon {
     pub(crate) const COLLECTION: &'static str = "discord_command_icon";
-    pub(crate) const MAX_RETRIES: u32 = 3;
+
+    /// Archive paths use backslashes and always end with one
+    pub fn normalize_output_path(path: &str) -> String {
+        let path = path.trim().replace('/', "\\");
+        if path.ends_with('\\') {
+            path
+        } else {
+            format!("{}\\", path)
+        }
+    }
+
+    /// Output paths this user chose recently, newest first
+    pub async fn recent_output_paths(
+        db: &mongodb::Database,
+        user_id: &str,
+    ) -> Result<Vec<String>, BotError> {
+        let collection: Collection<Document> = db.collection(Self::COLLECTION);
+
+        let jobs: Vec<Document> = collection
+            .find(doc! {
+                format!("{}.author.id", Self::MESSAGE): user_id,
+                Self::OUTPUT_PATH: { "$exists": true },
+            })
+            .projection(doc! { Self::OUTPUT_PATH: 1 })
+            .sort(doc! { Self::CREATED: -1 })
+            .limit(50)
+            .await?
+            .try_collect()
+            .await?;
+
+        let mut paths: Vec<String> = Vec::new();
+        for path in jobs.iter().filter_map(|job| job.get_str(Self::OUTPUT_PATH).ok()) {
+            if !paths.iter().any(|known| known == path) {
+                paths.push(path.to_string());
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Whether a border variant was requested for this job
+    pub fn renders_variant(&self, variant: &str) -> bool {
+        self.variants.is_empty() || self.variants.iter().any(|v| v == variant)
+    }
+
+    /// Find a job by its hex id, e.g. from a component custom_id
+    pub async fn find_by_id(db: &mongodb::Database, id: &str) -> Result<Option<Self>, BotError> {
+        let Ok(id) = ObjectId::parse_str(id) else {
+            return Ok(None);
+        };
+
+        let collection: Collection<JobIcon> = db.collection(Self::COLLECTION);
+        Ok(collection.find_one(doc! { "_id": id }).await?)
+    }
 
     /// Count total number of icon tasks
     pub async fn count_total(db: &mongodb::Database) -> Result<u64, BotError> {
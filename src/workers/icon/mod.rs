@@ -1,3 +1,3 @@
 pub mod handle;
 pub mod job;
-mod processor;
+pub(crate) mod processor;
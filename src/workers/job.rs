@@ -0,0 +1,336 @@
+//! Shared driver for conversion jobs: claiming, replies, error files, zipping, timing and status
+//!
+//! A converter implements [`Job`] for its Mongo document and gets a worker pool through
+//! [`JobProcessor`]; only the per-attachment conversion is its own.
+
+use crate::db::guild_config::{GuildConfig, Language};
+use crate::discord::discord::InteractionTarget;
+use crate::discord::message::attachment::{
+    AttachmentMemory, AttachmentVecExt, ensure_unique_filenames,
+};
+use crate::discord::message::component::ActionRow;
+use crate::discord::message::message::Message;
+use crate::discord::message::send::MessageSend;
+use crate::error::BotError;
+use crate::state;
+use crate::workers::lease;
+use crate::workers::processor::{TaskProcessor, notify_workers};
+use crate::workers::queue::QueueStatus;
+use crate::workers::retry::{self, FailedJob};
+use async_trait::async_trait;
+use bson::{Bson, doc, serialize_to_bson};
+use chrono::{DateTime, Utc};
+use image::RgbaImage;
+use mongodb::Collection;
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::io::{Cursor, Write};
+use std::marker::PhantomData;
+use zip::ZipWriter;
+use zip::write::FileOptions;
+
+/// Field names every job document shares
+pub mod field {
+    pub const STATUS: &str = "status";
+    pub const CREATED: &str = "created";
+    pub const COMPLETED: &str = "completed";
+    pub const REPLY: &str = "reply";
+    pub const ERROR: &str = "error";
+    pub const RETRY: &str = "retry";
+    pub const NOT_BEFORE: &str = "not_before";
+    pub const LOCKED_UNTIL: &str = "locked_until";
+    pub const WORKER_ID: &str = "worker_id";
+}
+
+/// File name (or archive path) and contents
+pub type OutputFile = (String, Vec<u8>);
+
+/// Results of one attachment
+#[derive(Default)]
+pub struct Converted {
+    /// Attached to the reply, or zipped
+    pub files: Vec<OutputFile>,
+    /// Rendered images a job summarizes in [`Job::package`], e.g. the icon collage
+    pub previews: Vec<RgbaImage>,
+}
+
+impl From<Vec<OutputFile>> for Converted {
+    fn from(files: Vec<OutputFile>) -> Self {
+        Self {
+            files,
+            previews: Vec::new(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait Job: Serialize + DeserializeOwned + Clone + Send + Sync + Unpin + 'static {
+    const COLLECTION: &'static str;
+    const POOL: &'static str;
+    const MAX_RETRIES: u32 = 3;
+    const MAX_WORKERS: usize = 1;
+
+    fn id(&self) -> Option<ObjectId>;
+    fn message(&self) -> &Message;
+    fn reply(&self) -> Option<&Message>;
+    fn interaction(&self) -> Option<&InteractionTarget>;
+    fn created(&self) -> DateTime<Utc>;
+    fn retry(&self) -> u32;
+    fn zip(&self) -> bool;
+
+    /// What happens to the images, e.g. "to BLP (quality: 80)"; used in the queued and done replies
+    fn describe(&self, language: Language) -> String;
+
+    /// Convert one downloaded attachment; an error turns into a `.error.txt` among the results
+    async fn convert(&self, attachment: &AttachmentMemory) -> Result<Converted, BotError>;
+
+    /// Name of the archive when results are zipped
+    fn archive_name(&self) -> String {
+        "converted_images.zip".to_string()
+    }
+
+    /// Files attached to the reply, everything in one archive when the job asks for a ZIP
+    ///
+    /// `previews` holds what every attachment rendered, in attachment order.
+    fn package(
+        &self,
+        files: Vec<OutputFile>,
+        _previews: Vec<RgbaImage>,
+    ) -> Result<Vec<OutputFile>, BotError> {
+        if !self.zip() {
+            return Ok(files);
+        }
+
+        Ok(vec![(self.archive_name(), zip_files(&files)?)])
+    }
+
+    /// Buttons and selects under the finished reply
+    fn components(&self, _file_count: usize) -> Option<Vec<ActionRow>> {
+        None
+    }
+}
+
+/// Worker pool for one kind of job
+pub struct JobProcessor<J>(PhantomData<fn() -> J>);
+
+#[async_trait]
+impl<J: Job> TaskProcessor for JobProcessor<J> {
+    const POOL: &'static str = J::POOL;
+    const DEFAULT_MAX_WORKERS: usize = J::MAX_WORKERS;
+
+    async fn process_queue_item() -> Result<bool, BotError> {
+        let db = state::db().await;
+        let collection: Collection<J> = db.collection(J::COLLECTION);
+
+        let result = collection
+            .find_one_and_update(
+                doc! {
+                    field::STATUS: QueueStatus::Pending.as_ref(),
+                    "$or": retry::ready(),
+                },
+                doc! {
+                    "$set": {
+                        field::STATUS: QueueStatus::Processing.as_ref(),
+                        field::LOCKED_UNTIL: lease::expiry(),
+                        field::WORKER_ID: lease::worker_id(),
+                    }
+                },
+            )
+            .sort(doc! { field::CREATED: 1 })
+            .return_document(mongodb::options::ReturnDocument::After)
+            .await?;
+
+        let Some(job) = result else {
+            return Ok(false);
+        };
+
+        let _lease = lease::hold(J::COLLECTION, job.id());
+
+        let failed = FailedJob {
+            collection: J::COLLECTION,
+            id: job.id(),
+            retry: job.retry(),
+            max_retries: J::MAX_RETRIES,
+            message: job.message(),
+            reply: job.reply(),
+            interaction: job.interaction(),
+        };
+
+        // Attempts lost to crashes count too, see `lease::recover`
+        if failed.exhausted() {
+            failed.give_up(&BotError::new("retries_exhausted")).await?;
+            return Ok(true);
+        }
+
+        if let Err(e) = Self::process(&job).await {
+            Self::on_error(&e);
+            failed.record::<Self>(&e).await?;
+        }
+
+        Ok(true)
+    }
+}
+
+impl<J: Job> JobProcessor<J> {
+    async fn process(job: &J) -> Result<(), BotError> {
+        let db = state::db().await;
+        let collection: Collection<J> = db.collection(J::COLLECTION);
+        let message = job.message();
+        let language = GuildConfig::for_guild(&db, message.guild_id.as_deref()).await?.language;
+
+        // First pass only posts the reply that later gets the results, then the job goes back in line
+        let Some(reply) = job.reply() else {
+            let (content, status) = if message.attachments.is_empty() {
+                (
+                    language
+                        .pick(
+                            "❌ No attachments found — nothing to convert.",
+                            "❌ Вложений нет — конвертировать нечего.",
+                        )
+                        .to_string(),
+                    QueueStatus::Completed,
+                )
+            } else {
+                let count = message.attachments.len();
+                (
+                    format!(
+                        "{} {}\n⏳ {}",
+                        language.pick(
+                            format!("✅ Added {} image(s) to conversion queue", count),
+                            format!("✅ В очередь добавлено изображений: {},", count),
+                        ),
+                        job.describe(language),
+                        language.pick("Processing...", "Обработка..."),
+                    ),
+                    QueueStatus::Pending,
+                )
+            };
+
+            let reply_msg = MessageSend {
+                content: Some(content),
+                message_reference: message.reply_reference(),
+                attachments: None,
+                components: None,
+            }
+            .reply(&message.channel_id, job.interaction())
+            .await?;
+
+            let mut update = doc! {
+                field::REPLY: serialize_to_bson(&reply_msg)?,
+                field::STATUS: status.as_ref(),
+            };
+            if status == QueueStatus::Completed {
+                update.insert(field::COMPLETED, Bson::DateTime(bson::DateTime::now()));
+            }
+
+            collection
+                .update_one(doc! { "_id": job.id() }, doc! { "$set": update })
+                .await?;
+
+            notify_workers::<Self>();
+            return Ok(());
+        };
+
+        let attachments = ensure_unique_filenames(message.attachments.clone())
+            .download_all(4)
+            .await;
+
+        let mut files = Vec::new();
+        let mut previews = Vec::new();
+        let mut converted = 0;
+
+        for attachment in attachments {
+            if let Some(ref error) = attachment.error {
+                files.push(error_file(&attachment, "downloading", error));
+                continue;
+            }
+
+            match job.convert(&attachment).await {
+                Ok(output) => {
+                    converted += 1;
+                    files.extend(output.files);
+                    previews.extend(output.previews);
+                }
+                Err(e) => files.push(error_file(&attachment, "processing", &e.tree())),
+            }
+        }
+
+        let conversion_time = format!(
+            "{:.2}s",
+            Utc::now()
+                .signed_duration_since(job.created())
+                .num_milliseconds() as f64
+                / 1000.0
+        );
+
+        let file_count = files.len();
+
+        MessageSend {
+            content: Some(format!(
+                "{} {}{}\n⏱️ {} {}",
+                language.pick(
+                    format!("✅ Converted {} image(s)", converted),
+                    format!("✅ Сконвертировано изображений: {},", converted),
+                ),
+                job.describe(language),
+                if job.zip() { language.pick(" (zipped)", " (в ZIP)") } else { "" },
+                language.pick("Completed in", "Готово за"),
+                conversion_time
+            )),
+            message_reference: None,
+            components: job.components(file_count),
+            attachments: Some(job.package(files, previews)?),
+        }
+        .update(&message.channel_id, reply, job.interaction())
+        .await?;
+
+        collection
+            .update_one(
+                doc! { "_id": job.id() },
+                doc! {
+                    "$set": {
+                        field::STATUS: QueueStatus::Completed.as_ref(),
+                        field::COMPLETED: Bson::DateTime(bson::DateTime::now())
+                    }
+                },
+            )
+            .await?;
+
+        notify_workers::<Self>();
+        Ok(())
+    }
+}
+
+/// Text file put in place of an attachment that couldn't be downloaded or converted
+fn error_file(attachment: &AttachmentMemory, stage: &str, details: &str) -> OutputFile {
+    let content = format!(
+        "Error {} file: {}\n\nError details:\n{}\n\nTimestamp: {}",
+        stage,
+        attachment.meta.filename,
+        details,
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
+    );
+
+    (format!("{}.error.txt", attachment.filename_stem), content.into_bytes())
+}
+
+/// Uncompressed archive, the images are already compressed
+pub fn zip_files(files: &[OutputFile]) -> Result<Vec<u8>, BotError> {
+    let mut zip_buffer = Vec::new();
+    {
+        let cursor = Cursor::new(&mut zip_buffer);
+        let mut zip = ZipWriter::new(cursor);
+        let options =
+            FileOptions::<()>::default().compression_method(zip::CompressionMethod::Stored);
+
+        for (filename, data) in files {
+            zip.start_file(filename, options)?;
+            zip.write_all(data)?;
+        }
+
+        zip.finish()?;
+    }
+
+    Ok(zip_buffer)
+}
@@ -0,0 +1,173 @@
+//! Leases on processing jobs, so a job whose worker died goes back to the queue
+
+use crate::error::BotError;
+use crate::state;
+use crate::workers::blp::job::JobBlp;
+use crate::workers::blp::processor::BlpProcessor;
+use crate::workers::icon::job::JobIcon;
+use crate::workers::icon::processor::IconProcessor;
+use crate::workers::job::field;
+use crate::workers::processor::notify_workers;
+use crate::workers::queue::QueueStatus;
+use crate::workers::rembg::job::JobRembg;
+use crate::workers::rembg::processor::RembgProcessor;
+use chrono::Utc;
+use mongodb::Collection;
+use mongodb::bson::{Document, doc, oid::ObjectId};
+use once_cell::sync::Lazy;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+
+/// How long a job stays ours without a renewal
+const LEASE: Duration = Duration::from_secs(120);
+
+/// Renewals come often enough that a slow network doesn't let a live lease expire
+const RENEW_EVERY: Duration = Duration::from_secs(30);
+
+const SWEEP_EVERY: Duration = Duration::from_secs(60);
+
+const JOB_COLLECTIONS: [&str; 3] = [JobBlp::COLLECTION, JobIcon::COLLECTION, JobRembg::COLLECTION];
+
+/// Unique per process, so a restarted bot never mistakes old leases for its own
+static WORKER_ID: Lazy<String> = Lazy::new(|| {
+    let host = std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "localhost".to_string());
+
+    let suffix = uuid::Uuid::new_v4().simple().to_string();
+    format!("{}-{}-{}", host, std::process::id(), &suffix[..8])
+});
+
+pub fn worker_id() -> &'static str {
+    WORKER_ID.as_str()
+}
+
+/// Lease end for a job claimed now
+pub fn expiry() -> bson::DateTime {
+    bson::DateTime::from_chrono(Utc::now() + LEASE)
+}
+
+/// Keeps the lease on a job alive until dropped
+pub struct Lease {
+    renewal: JoinHandle<()>,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.renewal.abort();
+    }
+}
+
+/// Renew the lease of a claimed job in the background while it is being processed
+pub fn hold(collection: &'static str, id: Option<ObjectId>) -> Lease {
+    let renewal = tokio::spawn(async move {
+        let Some(id) = id else {
+            return;
+        };
+
+        let mut ticker = interval(RENEW_EVERY);
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+
+            let db = state::db().await;
+            let jobs: Collection<Document> = db.collection(collection);
+
+            let result = jobs
+                .update_one(
+                    doc! {
+                        "_id": id,
+                        field::STATUS: QueueStatus::Processing.as_ref(),
+                        field::WORKER_ID: worker_id(),
+                    },
+                    doc! { "$set": { field::LOCKED_UNTIL: expiry() } },
+                )
+                .await;
+
+            match result {
+                // Finished, or the sweeper already handed the job to someone else
+                Ok(result) if result.matched_count == 0 => return,
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("[{}][ERROR]: lease renewal for {} failed: {:?}", collection, id, e)
+                }
+            }
+        }
+    });
+
+    Lease { renewal }
+}
+
+/// Return jobs with expired leases to the queue, the number of jobs recovered
+///
+/// A crashed attempt counts as a retry, so an image that kills the worker every time gives up eventually.
+/// Jobs claimed before leases existed have no `locked_until` and are recovered as well.
+pub async fn recover() -> Result<u64, BotError> {
+    let db = state::db().await;
+    let mut recovered = 0;
+
+    for name in JOB_COLLECTIONS {
+        let collection: Collection<Document> = db.collection(name);
+
+        let result = collection
+            .update_many(
+                doc! {
+                    field::STATUS: QueueStatus::Processing.as_ref(),
+                    "$or": [
+                        { field::LOCKED_UNTIL: { "$lt": bson::DateTime::now() } },
+                        { field::LOCKED_UNTIL: { "$exists": false } },
+                    ],
+                },
+                doc! {
+                    "$set": { field::STATUS: QueueStatus::Pending.as_ref() },
+                    "$unset": { field::LOCKED_UNTIL: "", field::WORKER_ID: "" },
+                    "$inc": { field::RETRY: 1 },
+                },
+            )
+            .await?;
+
+        if result.modified_count > 0 {
+            println!("[INFO] Recovered {} stuck job(s) in {}", result.modified_count, name);
+        }
+        recovered += result.modified_count;
+    }
+
+    Ok(recovered)
+}
+
+fn notify_all() {
+    notify_workers::<BlpProcessor>();
+    notify_workers::<IconProcessor>();
+    notify_workers::<RembgProcessor>();
+}
+
+/// Recover once at startup, then keep sweeping in the background
+pub fn spawn_sweeper() {
+    tokio::spawn(async {
+        let mut ticker = interval(SWEEP_EVERY);
+        let mut first = true;
+
+        loop {
+            ticker.tick().await;
+
+            let recovered = match recover().await {
+                Ok(recovered) => recovered,
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to recover stuck jobs:");
+                    e.print_tree();
+                    0
+                }
+            };
+
+            // Jobs queued before the restart need a kick too, not only the recovered ones
+            if first || recovered > 0 {
+                notify_all();
+            }
+            first = false;
+        }
+    });
+}
@@ -2,4 +2,9 @@ pub mod blp;
 pub mod icon;
 pub mod rembg;
 pub mod queue;
-pub(crate) mod processor;
\ No newline at end of file
+pub mod quota;
+pub(crate) mod processor;
+pub mod job;
+pub mod lease;
+pub mod retry;
+pub mod watch;
\ No newline at end of file
@@ -10,7 +10,21 @@ use tokio::sync::Notify;
 
 static POOLS: OnceCell<DashMap<&'static str, Arc<Pool>>> = OnceCell::new();
 fn pools() -> &'static DashMap<&'static str, Arc<Pool>> {
-    POOLS.get_or_init(|| DashMap::new())
+    POOLS.get_or_init(DashMap::new)
+}
+
+/// Pools this process runs workers for; every pool until restricted
+static ENABLED_POOLS: OnceCell<Vec<String>> = OnceCell::new();
+
+/// Run workers only for these pools, e.g. a gateway process enables none and just enqueues
+pub fn enable_pools(pools: Vec<String>) {
+    let _ = ENABLED_POOLS.set(pools);
+}
+
+pub fn pool_enabled(pool: &str) -> bool {
+    ENABLED_POOLS
+        .get()
+        .is_none_or(|pools| pools.iter().any(|enabled| enabled == pool))
 }
 
 struct Pool {
@@ -56,6 +70,11 @@ impl Drop for WorkerSlot {
 }
 
 pub fn notify_workers<P: TaskProcessor>() {
+    // Jobs for pools running elsewhere stay queued for that process
+    if !pool_enabled(P::POOL) {
+        return;
+    }
+
     let pool = match pools().entry(P::POOL) {
         Entry::Occupied(o) => o.get().clone(),
         Entry::Vacant(v) => {
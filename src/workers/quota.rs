@@ -0,0 +1,196 @@
+//! Per-user and per-guild job limits, checked before a job is queued
+
+use crate::db::guild_config::{GuildConfig, QuotaLimits};
+use crate::discord::discord::InteractionTarget;
+use crate::discord::message::message::Message;
+use crate::discord::message::send::MessageSend;
+use crate::discord::permissions;
+use crate::error::BotError;
+use crate::state;
+use crate::workers::blp::job::JobBlp;
+use crate::workers::icon::job::JobIcon;
+use crate::workers::queue::QueueStatus;
+use crate::workers::rembg::job::JobRembg;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::TryStreamExt;
+use mongodb::Collection;
+use mongodb::bson::{Document, doc};
+
+/// Every queue counts towards the same limits
+const JOB_COLLECTIONS: [&str; 3] = [JobBlp::COLLECTION, JobIcon::COLLECTION, JobRembg::COLLECTION];
+
+const USER_FIELD: &str = "message.author.id";
+const GUILD_FIELD: &str = "message.guild_id";
+
+/// Whether the job may be queued; when not, the user is told why and when to try again
+pub async fn admit(
+    message: &Message,
+    interaction: Option<&InteractionTarget>,
+) -> Result<bool, BotError> {
+    let db = state::db().await;
+    let config = GuildConfig::for_guild(&db, message.guild_id.as_deref()).await?;
+
+    if let Some(guild_id) = &message.guild_id {
+        let member_roles = message.member.as_ref().map(|member| member.roles.as_slice());
+        if permissions::is_guild_admin(guild_id, &message.author.id, member_roles.unwrap_or_default()) {
+            return Ok(true);
+        }
+    }
+
+    let Some(refusal) = check(&db, message, &config.quota).await? else {
+        return Ok(true);
+    };
+
+    MessageSend {
+        content: Some(refusal),
+        message_reference: message.reply_reference(),
+        attachments: None,
+        components: None,
+    }
+    .reply(&message.channel_id, interaction)
+    .await?;
+
+    Ok(false)
+}
+
+/// Refusal text for the first exceeded limit
+async fn check(
+    db: &mongodb::Database,
+    message: &Message,
+    limits: &QuotaLimits,
+) -> Result<Option<String>, BotError> {
+    let user_id = message.author.id.as_str();
+
+    if limits.pending_jobs > 0 {
+        let waiting = count(
+            db,
+            doc! {
+                USER_FIELD: user_id,
+                "status": { "$in": [QueueStatus::Pending.as_ref(), QueueStatus::Processing.as_ref()] },
+            },
+        )
+        .await?;
+
+        if waiting >= limits.pending_jobs as u64 {
+            return Ok(Some(format!(
+                "⏳ You already have {} jobs in the queue, try again once one of them is done",
+                waiting
+            )));
+        }
+    }
+
+    let hour = Duration::hours(1);
+
+    if limits.jobs_per_hour > 0 {
+        let filter = since(doc! { USER_FIELD: user_id }, hour);
+        if count(db, filter.clone()).await? >= limits.jobs_per_hour as u64 {
+            return Ok(Some(format!(
+                "🚦 You can start {} jobs per hour, try again {}",
+                limits.jobs_per_hour,
+                retry_at(db, filter, hour).await?
+            )));
+        }
+    }
+
+    if limits.guild_jobs_per_hour > 0
+        && let Some(guild_id) = &message.guild_id
+    {
+        let filter = since(doc! { GUILD_FIELD: guild_id }, hour);
+        if count(db, filter.clone()).await? >= limits.guild_jobs_per_hour as u64 {
+            return Ok(Some(format!(
+                "🚦 This server can start {} jobs per hour, try again {}",
+                limits.guild_jobs_per_hour,
+                retry_at(db, filter, hour).await?
+            )));
+        }
+    }
+
+    if limits.megabytes_per_day > 0 {
+        let day = Duration::days(1);
+        let filter = since(doc! { USER_FIELD: user_id }, day);
+
+        let incoming: u64 = message.attachments.iter().map(|attachment| attachment.size).sum();
+        let used = attachment_bytes(db, filter.clone()).await?;
+
+        if used + incoming > limits.megabytes_per_day as u64 * 1024 * 1024 {
+            return Ok(Some(format!(
+                "📦 You can send {} MB of images per day, try again {}",
+                limits.megabytes_per_day,
+                retry_at(db, filter, day).await?
+            )));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Restrict a filter to jobs created within the window
+fn since(mut filter: Document, window: Duration) -> Document {
+    let start = bson::DateTime::from_chrono(Utc::now() - window);
+    filter.insert("created", doc! { "$gte": start });
+    filter
+}
+
+async fn count(db: &mongodb::Database, filter: Document) -> Result<u64, BotError> {
+    let mut total = 0;
+
+    for name in JOB_COLLECTIONS {
+        let collection: Collection<Document> = db.collection(name);
+        total += collection.count_documents(filter.clone()).await?;
+    }
+
+    Ok(total)
+}
+
+/// Attachment bytes of the matching jobs; jobs queued without sizes count as zero
+async fn attachment_bytes(db: &mongodb::Database, filter: Document) -> Result<u64, BotError> {
+    let mut total = 0;
+
+    for name in JOB_COLLECTIONS {
+        let collection: Collection<Document> = db.collection(name);
+
+        let pipeline = vec![
+            doc! { "$match": filter.clone() },
+            doc! { "$unwind": "$message.attachments" },
+            doc! {
+                "$group": {
+                    "_id": null,
+                    "total": { "$sum": { "$toLong": "$message.attachments.size" } },
+                },
+            },
+        ];
+
+        if let Some(result) = collection.aggregate(pipeline).await?.try_next().await? {
+            total += result.get_i64("total").unwrap_or(0).max(0) as u64;
+        }
+    }
+
+    Ok(total)
+}
+
+/// When the oldest job in the window leaves it, as a Discord relative timestamp
+async fn retry_at(
+    db: &mongodb::Database,
+    filter: Document,
+    window: Duration,
+) -> Result<String, BotError> {
+    let mut oldest: Option<DateTime<Utc>> = None;
+
+    for name in JOB_COLLECTIONS {
+        let collection: Collection<Document> = db.collection(name);
+
+        let job = collection
+            .find_one(filter.clone())
+            .sort(doc! { "created": 1 })
+            .projection(doc! { "created": 1 })
+            .await?;
+
+        if let Some(created) = job.and_then(|job| job.get_datetime("created").ok().copied()) {
+            let created = created.to_chrono();
+            oldest = Some(oldest.map_or(created, |oldest| oldest.min(created)));
+        }
+    }
+
+    let retry = oldest.unwrap_or_else(Utc::now) + window;
+    Ok(format!("<t:{}:R>", retry.timestamp()))
+}
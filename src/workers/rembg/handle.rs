@@ -1,22 +1,34 @@
+use crate::discord::discord::InteractionTarget;
 use crate::discord::message::handle::CommandArgs;
 use crate::discord::message::message::Message;
 use crate::error::BotError;
 use crate::state;
 use crate::workers::processor::notify_workers;
+use crate::workers::quota;
 use crate::workers::rembg::job::JobRembg;
 use crate::workers::rembg::processor::RembgProcessor;
 use mongodb::Collection;
 
-pub async fn handle(message: Message, args: &CommandArgs) -> Result<(), BotError> {
+pub async fn handle(
+    message: Message,
+    args: &CommandArgs,
+    interaction: Option<InteractionTarget>,
+) -> Result<(), BotError> {
+    if !quota::admit(&message, interaction.as_ref()).await? {
+        return Ok(());
+    }
+
     let db = state::db().await;
     let collection: Collection<JobRembg> = db.collection(JobRembg::COLLECTION);
 
     collection
         .insert_one(JobRembg {
             message,
+            interaction,
             threshold: args.threshold,
             binary: args.binary,
             mask: args.mask,
+            model: args.model.clone(),
             zip: args.zip,
             created: chrono::Utc::now(),
             ..Default::default()
@@ -1,3 +1,4 @@
+use crate::discord::discord::InteractionTarget;
 use crate::discord::message::message::Message;
 use crate::error::BotError;
 use crate::workers::queue::QueueStatus;
@@ -20,12 +21,20 @@ pub struct JobRembg {
 
     pub reply: Option<Message>,
 
+    /// Slash command jobs reply through the interaction webhook instead of the channel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interaction: Option<InteractionTarget>,
+
     pub threshold: u8,
 
     pub binary: bool,
 
     pub mask: bool,
 
+    /// Model name from `models/`; the default model when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
     pub zip: bool,
 
     pub status: QueueStatus,
@@ -37,13 +46,40 @@ pub struct JobRembg {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub completed: Option<DateTime<Utc>>,
 
+    /// Error tree of the last failed attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
     #[serde(default)]
     pub retry: u32,
+
+    /// Backoff after a failed attempt, the job isn't claimed again before this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<datetime::FromChrono04DateTime>")]
+    pub not_before: Option<DateTime<Utc>>,
+
+    /// Processing lease end; the sweeper returns the job to the queue once it passes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<datetime::FromChrono04DateTime>")]
+    pub locked_until: Option<DateTime<Utc>>,
+
+    /// Process holding the lease
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_id: Option<String>,
 }
 
 impl JobRembg {
     pub(crate) const COLLECTION: &'static str = "discord_command_rembg";
-    pub(crate) const MAX_RETRIES: u32 = 3;
+
+    /// Find a job by its hex id, e.g. from a component custom_id
+    pub async fn find_by_id(db: &mongodb::Database, id: &str) -> Result<Option<Self>, BotError> {
+        let Ok(id) = ObjectId::parse_str(id) else {
+            return Ok(None);
+        };
+
+        let collection: Collection<JobRembg> = db.collection(Self::COLLECTION);
+        Ok(collection.find_one(doc! { "_id": id }).await?)
+    }
 
     /// Count total number of rembg tasks
     pub async fn count_total(db: &mongodb::Database) -> Result<u64, BotError> {
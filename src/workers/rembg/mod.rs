@@ -1,3 +1,4 @@
 pub mod handle;
-mod processor;
-pub mod job;
\ No newline at end of file
+pub(crate) mod processor;
+pub mod job;
+pub mod models;
\ No newline at end of file
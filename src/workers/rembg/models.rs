@@ -0,0 +1,75 @@
+use crate::error::BotError;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use rembg_rs::manager::ModelManager;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Directory the model download fills with `.onnx` files
+pub const MODELS_DIR: &str = "models";
+
+/// Model used when a job doesn't name one
+pub const DEFAULT_MODEL: &str = "u2net";
+
+/// Loaded models by name, each initialized on first use
+static MODEL_MANAGERS: Lazy<DashMap<String, Arc<ModelManager>>> = Lazy::new(DashMap::new);
+
+/// How long a listing of `models/` stays fresh; downloads show up after at most this long
+const LISTING_TTL: Duration = Duration::from_secs(30);
+
+/// Model names and when they were read from disk
+type Listing = Option<(Instant, Vec<String>)>;
+
+static MODEL_LISTING: Lazy<Mutex<Listing>> = Lazy::new(|| Mutex::new(None));
+
+/// Names of the `.onnx` models present in `models/`, sorted
+///
+/// Autocomplete asks on every keystroke, so the listing is reused for a while.
+pub fn available_models() -> Vec<String> {
+    let mut listing = MODEL_LISTING.lock().unwrap_or_else(PoisonError::into_inner);
+
+    if let Some((listed, models)) = listing.as_ref()
+        && listed.elapsed() < LISTING_TTL
+    {
+        return models.clone();
+    }
+
+    let models = list_models();
+    *listing = Some((Instant::now(), models.clone()));
+    models
+}
+
+fn list_models() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(MODELS_DIR) else {
+        return Vec::new();
+    };
+
+    let mut models: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "onnx"))
+        .filter_map(|path| path.file_stem()?.to_str().map(String::from))
+        .collect();
+
+    models.sort();
+    models
+}
+
+/// Get the loaded model, loading it from `models/<name>.onnx` on first use
+pub fn model_manager(name: &str) -> Result<Arc<ModelManager>, BotError> {
+    if let Some(manager) = MODEL_MANAGERS.get(name) {
+        return Ok(manager.clone());
+    }
+
+    // Only names found on disk, so a job can't point the loader at arbitrary paths
+    if !available_models().iter().any(|model| model == name) {
+        return Err(BotError::new("rembg_model_not_found").push_str(name.to_string()));
+    }
+
+    let path = Path::new(MODELS_DIR).join(format!("{}.onnx", name));
+    let manager = Arc::new(ModelManager::from_file(path.as_path())?);
+
+    MODEL_MANAGERS.insert(name.to_string(), manager.clone());
+    Ok(manager)
+}
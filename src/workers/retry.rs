@@ -0,0 +1,149 @@
+//! Retry accounting for failed jobs: backoff between attempts, then a terminal Failed status
+
+use crate::db::guild_config::GuildConfig;
+use crate::discord::discord::InteractionTarget;
+use crate::discord::message::message::Message;
+use crate::discord::message::send::MessageSend;
+use crate::error::BotError;
+use crate::state;
+use crate::workers::job::field;
+use crate::workers::processor::{TaskProcessor, notify_workers};
+use crate::workers::queue::QueueStatus;
+use chrono::Utc;
+use mongodb::Collection;
+use mongodb::bson::{Bson, Document, bson, doc, oid::ObjectId};
+use tokio::time::Duration;
+
+/// Wait before the first retry, doubled for every retry after it
+const BACKOFF: Duration = Duration::from_secs(30);
+
+/// `$or` clause for claim filters that skips jobs still waiting out their backoff
+pub fn ready() -> Bson {
+    bson!([
+        { field::NOT_BEFORE: { "$exists": false } },
+        { field::NOT_BEFORE: { "$lte": bson::DateTime::now() } },
+    ])
+}
+
+/// The parts of a job a failure needs
+pub struct FailedJob<'a> {
+    pub collection: &'static str,
+    pub id: Option<ObjectId>,
+    pub retry: u32,
+    pub max_retries: u32,
+    pub message: &'a Message,
+    pub reply: Option<&'a Message>,
+    pub interaction: Option<&'a InteractionTarget>,
+}
+
+impl FailedJob<'_> {
+    /// Whether earlier attempts, e.g. ones lost to a crash, already used up the retries
+    pub fn exhausted(&self) -> bool {
+        self.retry >= self.max_retries
+    }
+
+    /// Count the attempt and queue the job again after a backoff, or give up once it was the last one
+    pub async fn record<P: TaskProcessor>(&self, error: &BotError) -> Result<(), BotError> {
+        let retry = self.retry + 1;
+        if retry >= self.max_retries {
+            return self.give_up(error).await;
+        }
+
+        let delay = BACKOFF * 2u32.pow(self.retry);
+        let not_before = Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default();
+
+        self.collection()
+            .await
+            .update_one(
+                doc! { "_id": self.id },
+                doc! {
+                    "$set": {
+                        field::STATUS: QueueStatus::Pending.as_ref(),
+                        field::RETRY: retry,
+                        field::ERROR: error.tree(),
+                        field::NOT_BEFORE: bson::DateTime::from_chrono(not_before),
+                    },
+                    "$unset": { field::LOCKED_UNTIL: "", field::WORKER_ID: "" },
+                },
+            )
+            .await?;
+
+        println!(
+            "[{}][INFO] Attempt {} of {} failed, retrying in {}s",
+            P::POOL,
+            retry,
+            self.max_retries,
+            delay.as_secs()
+        );
+
+        // Nothing else wakes the pool when the queue is otherwise idle
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            notify_workers::<P>();
+        });
+
+        Ok(())
+    }
+
+    /// Mark the job Failed and tell the user in the reply
+    pub async fn give_up(&self, error: &BotError) -> Result<(), BotError> {
+        self.collection()
+            .await
+            .update_one(
+                doc! { "_id": self.id },
+                doc! {
+                    "$set": {
+                        field::STATUS: QueueStatus::Failed.as_ref(),
+                        field::RETRY: self.retry.max(self.max_retries),
+                        field::ERROR: error.tree(),
+                    },
+                    "$unset": {
+                        field::NOT_BEFORE: "",
+                        field::LOCKED_UNTIL: "",
+                        field::WORKER_ID: "",
+                    },
+                },
+            )
+            .await?;
+
+        let db = state::db().await;
+        let language =
+            GuildConfig::for_guild(&db, self.message.guild_id.as_deref()).await?.language;
+
+        let mut notice = MessageSend {
+            content: Some(language.pick(
+                format!(
+                    "❌ Processing failed after {} attempts (`{}`), nothing was converted.\n\
+                     Check that the images open correctly and try again.",
+                    self.max_retries, error.key
+                ),
+                format!(
+                    "❌ Обработка не удалась после {} попыток (`{}`), ничего не сконвертировано.\n\
+                     Проверьте, что изображения открываются, и попробуйте снова.",
+                    self.max_retries, error.key
+                ),
+            )),
+            message_reference: None,
+            attachments: None,
+            components: None,
+        };
+
+        match self.reply {
+            Some(reply) => {
+                notice
+                    .update(&self.message.channel_id, reply, self.interaction)
+                    .await?
+            }
+            None => {
+                notice.message_reference = self.message.reply_reference();
+                notice.reply(&self.message.channel_id, self.interaction).await?
+            }
+        };
+
+        Ok(())
+    }
+
+    async fn collection(&self) -> Collection<Document> {
+        state::db().await.collection(self.collection)
+    }
+}
@@ -0,0 +1,141 @@
+//! Wake local workers for jobs queued by other bot instances sharing the database
+//!
+//! `notify_workers` only reaches the process that inserted the job. With several instances
+//! each one watches the job collections: a change stream where the deployment supports it,
+//! polling on a standalone server, which has no change streams.
+
+use crate::error::BotError;
+use crate::state;
+use crate::workers::blp::job::JobBlp;
+use crate::workers::icon::job::JobIcon;
+use crate::workers::job::{Job, JobProcessor, field};
+use crate::workers::processor::{notify_workers, pool_enabled};
+use crate::workers::queue::QueueStatus;
+use crate::workers::rembg::job::JobRembg;
+use futures_util::StreamExt;
+use mongodb::Collection;
+use mongodb::bson::{Document, doc};
+use mongodb::error::ErrorKind;
+use std::str::FromStr;
+use tokio::time::{Duration, interval, sleep};
+
+/// Server error for `$changeStream` outside a replica set or sharded cluster
+const CHANGE_STREAMS_UNSUPPORTED: i32 = 40573;
+
+const POLL_EVERY: Duration = Duration::from_secs(5);
+
+/// Pause before reopening a change stream that broke, e.g. on a primary step-down
+const REOPEN_AFTER: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchMode {
+    /// Single instance, local notifications are enough
+    Off,
+    /// Change streams, polling when the server has none
+    Auto,
+    /// Polling only
+    Poll,
+}
+
+impl FromStr for WatchMode {
+    type Err = BotError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "" | "0" | "false" | "off" => Ok(WatchMode::Off),
+            "1" | "true" | "auto" | "stream" => Ok(WatchMode::Auto),
+            "poll" => Ok(WatchMode::Poll),
+            _ => Err(BotError::new("job_watch_mode").push_str(format!(
+                "Unknown JOB_WATCH value '{}', expected off, auto or poll",
+                value
+            ))),
+        }
+    }
+}
+
+/// Start a watcher for every job collection
+pub fn spawn_all(mode: WatchMode) {
+    if mode == WatchMode::Off {
+        return;
+    }
+
+    spawn::<JobBlp>(mode);
+    spawn::<JobIcon>(mode);
+    spawn::<JobRembg>(mode);
+}
+
+fn spawn<J: Job>(mode: WatchMode) {
+    if !pool_enabled(J::POOL) {
+        return;
+    }
+
+    tokio::spawn(async move {
+        if mode == WatchMode::Auto {
+            loop {
+                match watch::<J>().await {
+                    Err(e) if is_unsupported(&e) => {
+                        println!(
+                            "[{}][INFO] Change streams need a replica set, polling every {}s",
+                            J::POOL,
+                            POLL_EVERY.as_secs()
+                        );
+                        break;
+                    }
+                    Err(e) => {
+                        eprintln!("[{}][ERROR] Change stream failed, reopening:", J::POOL);
+                        BotError::from(e).print_tree();
+                    }
+                    Ok(()) => {}
+                }
+                sleep(REOPEN_AFTER).await;
+            }
+        }
+
+        poll::<J>().await;
+    });
+}
+
+/// Notify on inserts and on jobs going back to pending, e.g. a retry or a recovered lease
+async fn watch<J: Job>() -> Result<(), mongodb::error::Error> {
+    let db = state::db().await;
+    let collection: Collection<Document> = db.collection(J::COLLECTION);
+
+    let pending = QueueStatus::Pending.as_ref();
+    let mut stream = collection
+        .watch()
+        .pipeline([doc! {
+            "$match": {
+                "$or": [
+                    { "operationType": "insert" },
+                    { format!("updateDescription.updatedFields.{}", field::STATUS): pending },
+                ]
+            }
+        }])
+        .await?;
+
+    println!("[{}][INFO] Watching {} for new jobs", J::POOL, J::COLLECTION);
+
+    // Jobs queued while the stream was closed
+    notify_workers::<JobProcessor<J>>();
+
+    while let Some(event) = stream.next().await {
+        event?;
+        notify_workers::<JobProcessor<J>>();
+    }
+
+    Ok(())
+}
+
+/// Wake the pool periodically; an idle worker only runs one empty claim query
+async fn poll<J: Job>() {
+    let mut ticker = interval(POLL_EVERY);
+
+    loop {
+        ticker.tick().await;
+        notify_workers::<JobProcessor<J>>();
+    }
+}
+
+fn is_unsupported(e: &mongodb::error::Error) -> bool {
+    matches!(*e.kind, ErrorKind::Command(ref command) if command.code == CHANGE_STREAMS_UNSUPPORTED)
+}